@@ -0,0 +1,31 @@
+use std::{env, fs, path::PathBuf, process};
+
+use chip8_rs::emulator::assembler::assemble_file;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let (input, output) = match &args[1..] {
+        [input] => (
+            PathBuf::from(input),
+            PathBuf::from(input).with_extension("ch8"),
+        ),
+        [input, flag, output] if flag == "-o" => (PathBuf::from(input), PathBuf::from(output)),
+        _ => {
+            eprintln!("Usage: chip8-asm <source> [-o <rom>]");
+            process::exit(1);
+        }
+    };
+
+    let rom = match assemble_file(&input) {
+        Ok(rom) => rom,
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    };
+
+    if let Err(error) = fs::write(&output, &rom) {
+        eprintln!("Could not write {}: {}", output.display(), error);
+        process::exit(1);
+    }
+}
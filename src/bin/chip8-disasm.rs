@@ -0,0 +1,23 @@
+use std::{env, fs, process};
+
+use chip8_rs::emulator::{disassembler::disassemble, emulator::ROM_OFFSET};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let Some(romfile) = args.get(1) else {
+        eprintln!("Usage: chip8-disasm <rom>");
+        process::exit(1);
+    };
+
+    let rom = match fs::read(romfile) {
+        Ok(rom) => rom,
+        Err(error) => {
+            eprintln!("Could not read {}: {}", romfile, error);
+            process::exit(1);
+        }
+    };
+
+    for line in disassemble(&rom, ROM_OFFSET) {
+        println!("{}", line);
+    }
+}
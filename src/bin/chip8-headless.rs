@@ -0,0 +1,232 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use chip8_rs::emulator::{
+    emulator::{Chip8Emulator, EmulatorMode, FrameOutcome},
+    headless::{HeadlessRunner, KeyPress, Stop},
+    movie::{Movie, MoviePlayer},
+    quirks::Platform,
+    random::Generator,
+    timing::Timing,
+};
+
+const USAGE: &str = "\
+Usage: chip8-headless <rom> [options]
+
+Runs a ROM without a window until a limit is reached or the program ends, loops or
+faults, then prints the screen and registers.
+
+Options:
+  --platform <name>        chip8, chip48, schip or xochip
+  --cycles <n>             stop at the end of the frame that ran the nth instruction
+  --frames <n>             stop after n frames, 600 when no limit is given
+  --ipf <n>                instructions per frame, 11 by default
+  --timing <name>          fixed, or vip to charge COSMAC VIP cycles instead of --ipf
+  --seed <n>               random seed, 0 by default
+  --rng <name>             random generator, xorshift or vip-style
+  --key <frame>:<key>[:n]  hold keypad key (hex) from frame for n frames, 6 by default
+  --play <movie>           replay a recorded movie instead, exit 1 if it desyncs
+  --png <file>             write the screen to a PNG instead of printing it
+  --scale <n>              PNG pixel size, 1 by default";
+
+const DEFAULT_FRAMES: u64 = 600;
+const DEFAULT_KEY_FRAMES: u64 = 6;
+
+struct Options {
+    rom: PathBuf,
+    platform: Option<Platform>,
+    cycles: Option<u64>,
+    frames: Option<u64>,
+    instructions_per_frame: Option<u32>,
+    timing: Timing,
+    seed: u64,
+    generator: Generator,
+    keys: Vec<KeyPress>,
+    play: Option<PathBuf>,
+    png: Option<PathBuf>,
+    scale: usize,
+}
+
+fn main() {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let rom = match fs::read(&options.rom) {
+        Ok(rom) => rom,
+        Err(error) => {
+            eprintln!("Could not read {}: {}", options.rom.display(), error);
+            process::exit(2);
+        }
+    };
+
+    let mut chip8 = Chip8Emulator::new(EmulatorMode::Run);
+    if let Some(platform) = options.platform {
+        chip8.set_platform(platform);
+    }
+    chip8.timing = options.timing;
+    chip8.seed_random(options.generator, options.seed);
+    if let Err(error) = chip8.load_rom(&rom) {
+        eprintln!("Could not load {}: {}", options.rom.display(), error);
+        process::exit(2);
+    }
+
+    if let Some(instructions_per_frame) = options.instructions_per_frame {
+        chip8.instructions_per_frame = instructions_per_frame;
+    }
+
+    let mut runner = HeadlessRunner::new(chip8);
+    runner.keys = options.keys;
+
+    let frames = match (options.cycles, options.frames) {
+        (None, None) => Some(DEFAULT_FRAMES),
+        (_, frames) => frames,
+    };
+    let (status, failed) = match &options.play {
+        Some(path) => play(&mut runner.chip8, path),
+        None => {
+            let stop = runner.run(options.cycles, frames);
+            let status = format!(
+                "{} after {} frames, {} cycles",
+                stop, runner.frame, runner.cycles
+            );
+            (status, matches!(stop, Stop::Fault(_)))
+        }
+    };
+
+    println!("{}", status);
+    match &options.png {
+        Some(path) => {
+            if let Err(error) = runner.write_png(path, options.scale) {
+                eprintln!("Could not write {}: {}", path.display(), error);
+                process::exit(2);
+            }
+        }
+        None => print!("{}", runner.screen_ascii()),
+    }
+    println!("{}", runner.chip8.registers());
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+// Replays a movie to its end, a fault or an exit, and checks the final state matches the
+// recording
+fn play(chip8: &mut Chip8Emulator, path: &Path) -> (String, bool) {
+    let movie = Movie::load(path).and_then(|movie| movie.apply(chip8).map(|()| movie));
+    let mut player = match movie {
+        Ok(movie) => MoviePlayer::new(movie),
+        Err(error) => {
+            eprintln!("Could not play {}: {}", path.display(), error);
+            process::exit(2);
+        }
+    };
+
+    while !player.is_finished(chip8) {
+        player.press_keys(chip8);
+        match chip8.tick_frame() {
+            Ok(FrameOutcome::Exit) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
+
+    match player.movie.verify(chip8) {
+        Ok(()) => (
+            format!("Playback matches after {} frames", chip8.frame()),
+            false,
+        ),
+        Err(error) => (error.to_string(), true),
+    }
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut args = env::args().skip(1);
+    let mut rom = None;
+    let mut options = Options {
+        rom: PathBuf::new(),
+        platform: None,
+        cycles: None,
+        frames: None,
+        instructions_per_frame: None,
+        timing: Timing::Fixed,
+        seed: 0,
+        generator: Generator::default(),
+        keys: Vec::new(),
+        play: None,
+        png: None,
+        scale: 1,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--platform" => options.platform = Some(value()?.parse()?),
+            "--cycles" => options.cycles = Some(parse_number(&value()?)?),
+            "--frames" => options.frames = Some(parse_number(&value()?)?),
+            "--ipf" => options.instructions_per_frame = Some(parse_number(&value()?)?),
+            "--timing" => options.timing = value()?.parse()?,
+            "--seed" => options.seed = parse_number(&value()?)?,
+            "--rng" => options.generator = value()?.parse()?,
+            "--key" => options.keys.push(parse_key(&value()?)?),
+            "--play" => options.play = Some(PathBuf::from(value()?)),
+            "--png" => options.png = Some(PathBuf::from(value()?)),
+            "--scale" => options.scale = parse_number(&value()?)?,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {}", arg)),
+            _ if rom.is_none() => rom = Some(PathBuf::from(arg)),
+            _ => return Err(format!("Unexpected argument {}", arg)),
+        }
+    }
+
+    options.rom = rom.ok_or("No ROM given")?;
+    Ok(options)
+}
+
+fn parse_number<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.parse()
+        .map_err(|_| format!("Invalid number '{}'", text))
+}
+
+// frame:key[:frames], the key in hex like the keypad
+fn parse_key(text: &str) -> Result<KeyPress, String> {
+    let invalid = || format!("Invalid key press '{}', expected frame:key[:frames]", text);
+    let mut fields = text.split(':');
+
+    let frame = fields
+        .next()
+        .ok_or_else(invalid)?
+        .parse()
+        .map_err(|_| invalid())?;
+    let key = fields
+        .next()
+        .and_then(|key| u8::from_str_radix(key, 16).ok())
+        .filter(|&key| key <= 0xF)
+        .ok_or_else(invalid)?;
+    let frames = match fields.next() {
+        Some(frames) => frames.parse().map_err(|_| invalid())?,
+        None => DEFAULT_KEY_FRAMES,
+    };
+    // A key has to be held for a frame for the program to see it
+    if frames == 0 {
+        return Err(format!(
+            "Invalid key press '{}', frames must be at least 1",
+            text
+        ));
+    }
+    if fields.next().is_some() {
+        return Err(invalid());
+    }
+
+    Ok(KeyPress { frame, key, frames })
+}
@@ -0,0 +1,467 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Deserializer, de};
+
+use crate::{
+    emulator::{
+        quirks::{Platform, Quirks},
+        timing::Timing,
+    },
+    frontend::{
+        keymap::Keymap,
+        palette::{Palette, parse_color},
+    },
+};
+
+// Limits of the settings, the command line checks the same ones
+pub const MAX_INSTRUCTIONS_PER_FRAME: u32 = 100_000;
+pub const MAX_SCALE: u32 = 64;
+pub const MAX_GRID: u32 = 16;
+
+// Settings from config.toml in the user's config directory, for every ROM and per ROM by
+// the SHA-1 of the ROM. Values are named like on the command line, which overrides them:
+//   platform = "chip8"
+//   quirks = ["shift", "jump"]
+//   ipf = 15
+//   scale = 8
+//   palette = "amber"
+//   grid = 1
+//
+//   [audio]
+//   volume = 0.5
+//
+//   [keys]
+//   5 = ["W", "Up"]
+//
+//   [roms.0123456789abcdef0123456789abcdef01234567]
+//   platform = "schip"
+//   ipf = 30
+//
+//   [roms.0123456789abcdef0123456789abcdef01234567.keys]
+//   4 = "Left"
+//   6 = "Right"
+// quirks lists the quirks that are set, replacing the platform's. palette is a theme or a
+// list of up to four colours, foreground and background replace its first two colours.
+// Keypad keys are mapped to one host key or a list of them. A keypad key listed in a
+// section loses the host keys it had before that section. Game controller sticks ignore
+// movements within the deadzone, a fraction of the full range.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub deadzone: Option<f32>,
+    #[serde(default, deserialize_with = "parse")]
+    pub platform: Option<Platform>,
+    #[serde(default, deserialize_with = "quirks")]
+    pub quirks: Option<Quirks>,
+    #[serde(
+        default,
+        deserialize_with = "ranged::<_, 1, MAX_INSTRUCTIONS_PER_FRAME>"
+    )]
+    pub ipf: Option<u32>,
+    #[serde(default, deserialize_with = "parse")]
+    pub timing: Option<Timing>,
+    #[serde(default, deserialize_with = "ranged::<_, 1, MAX_SCALE>")]
+    pub scale: Option<u32>,
+    #[serde(default, deserialize_with = "palette")]
+    pub palette: Option<Palette>,
+    #[serde(default, deserialize_with = "color")]
+    pub foreground: Option<u32>,
+    #[serde(default, deserialize_with = "color")]
+    pub background: Option<u32>,
+    #[serde(default, deserialize_with = "ranged::<_, 0, MAX_GRID>")]
+    pub grid: Option<u32>,
+    #[serde(default)]
+    pub audio: AudioConfig,
+    #[serde(default)]
+    pub keys: BTreeMap<String, HostKeys>,
+    #[serde(default)]
+    pub roms: HashMap<String, RomConfig>,
+}
+
+// Settings for one ROM, on top of the ones for every ROM
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RomConfig {
+    #[serde(default, deserialize_with = "parse")]
+    pub platform: Option<Platform>,
+    #[serde(default, deserialize_with = "quirks")]
+    pub quirks: Option<Quirks>,
+    #[serde(
+        default,
+        deserialize_with = "ranged::<_, 1, MAX_INSTRUCTIONS_PER_FRAME>"
+    )]
+    pub ipf: Option<u32>,
+    #[serde(default, deserialize_with = "parse")]
+    pub timing: Option<Timing>,
+    #[serde(default, deserialize_with = "ranged::<_, 1, MAX_SCALE>")]
+    pub scale: Option<u32>,
+    #[serde(default, deserialize_with = "palette")]
+    pub palette: Option<Palette>,
+    #[serde(default, deserialize_with = "color")]
+    pub foreground: Option<u32>,
+    #[serde(default, deserialize_with = "color")]
+    pub background: Option<u32>,
+    #[serde(default, deserialize_with = "ranged::<_, 0, MAX_GRID>")]
+    pub grid: Option<u32>,
+    #[serde(default)]
+    pub audio: AudioConfig,
+    #[serde(default)]
+    pub keys: BTreeMap<String, HostKeys>,
+}
+
+// Volume is a fraction of the full volume
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AudioConfig {
+    pub mute: Option<bool>,
+    pub volume: Option<f32>,
+}
+
+// The settings that apply to a ROM, None where neither its section nor the settings for
+// every ROM have a value
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Settings {
+    pub platform: Option<Platform>,
+    pub quirks: Option<Quirks>,
+    pub ipf: Option<u32>,
+    pub timing: Option<Timing>,
+    pub scale: Option<u32>,
+    pub palette: Option<Palette>,
+    pub foreground: Option<u32>,
+    pub background: Option<u32>,
+    pub grid: Option<u32>,
+    pub mute: Option<bool>,
+    pub volume: Option<f32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum HostKeys {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    // A keypad key that is not 0 to F
+    InvalidKey(String),
+}
+
+impl Settings {
+    // These settings, with the other ones filling in what they leave out
+    pub fn or(self, other: Settings) -> Settings {
+        Settings {
+            platform: self.platform.or(other.platform),
+            quirks: self.quirks.or(other.quirks),
+            ipf: self.ipf.or(other.ipf),
+            timing: self.timing.or(other.timing),
+            scale: self.scale.or(other.scale),
+            palette: self.palette.or(other.palette),
+            foreground: self.foreground.or(other.foreground),
+            background: self.background.or(other.background),
+            grid: self.grid.or(other.grid),
+            mute: self.mute.or(other.mute),
+            volume: self.volume.or(other.volume),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(error) => write!(f, "{}", error),
+            ConfigError::Parse(error) => write!(f, "{}", error),
+            ConfigError::InvalidKey(key) => {
+                write!(f, "Invalid keypad key '{}', expected 0 to F", key)
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(error: io::Error) -> Self {
+        ConfigError::Io(error)
+    }
+}
+
+impl Config {
+    // chip8-rs/config.toml in the platform's config directory, such as ~/.config on Linux
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("chip8-rs").join("config.toml"))
+    }
+
+    // The defaults when the file does not exist
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Config::parse(&text),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        toml::from_str(text).map_err(ConfigError::Parse)
+    }
+
+    // The section for a ROM, by its SHA-1 in hex
+    pub fn rom(&self, rom_id: &str) -> Option<&RomConfig> {
+        self.roms
+            .iter()
+            .find(|(id, _)| id.eq_ignore_ascii_case(rom_id))
+            .map(|(_, rom)| rom)
+    }
+
+    // The settings for a ROM: its section's, then the ones known about the ROM from
+    // elsewhere, such as the ROM database, then the ones for every ROM
+    pub fn settings(&self, rom_id: &str, known: Settings) -> Settings {
+        let global = Settings {
+            platform: self.platform,
+            quirks: self.quirks,
+            ipf: self.ipf,
+            timing: self.timing,
+            scale: self.scale,
+            palette: self.palette,
+            foreground: self.foreground,
+            background: self.background,
+            grid: self.grid,
+            mute: self.audio.mute,
+            volume: self.audio.volume,
+        };
+        let rom = self
+            .rom(rom_id)
+            .map_or_else(Settings::default, |rom| Settings {
+                platform: rom.platform,
+                quirks: rom.quirks,
+                ipf: rom.ipf,
+                timing: rom.timing,
+                scale: rom.scale,
+                palette: rom.palette,
+                foreground: rom.foreground,
+                background: rom.background,
+                grid: rom.grid,
+                mute: rom.audio.mute,
+                volume: rom.audio.volume,
+            });
+        rom.or(known).or(global)
+    }
+
+    // The given keymap with the keys for every ROM and then the ones for this ROM applied
+    pub fn keymap(&self, rom_id: &str, mut keymap: Keymap) -> Result<Keymap, ConfigError> {
+        apply_keys(&mut keymap, &self.keys)?;
+        if let Some(rom) = self.rom(rom_id) {
+            apply_keys(&mut keymap, &rom.keys)?;
+        }
+        Ok(keymap)
+    }
+}
+
+fn apply_keys(keymap: &mut Keymap, keys: &BTreeMap<String, HostKeys>) -> Result<(), ConfigError> {
+    for (key, names) in keys {
+        let key = u8::from_str_radix(key, 16)
+            .ok()
+            .filter(|&key| key <= 0xF)
+            .ok_or_else(|| ConfigError::InvalidKey(key.clone()))?;
+        match names {
+            HostKeys::One(name) => keymap.rebind(key, std::slice::from_ref(name)),
+            HostKeys::Many(names) => keymap.rebind(key, names),
+        }
+    }
+    Ok(())
+}
+
+// Reads a setting from its name, like the command line does
+pub(crate) fn parse<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = String>,
+{
+    let text = String::deserialize(deserializer)?;
+    text.parse().map(Some).map_err(de::Error::custom)
+}
+
+pub(crate) fn ranged<'de, D, const MIN: u32, const MAX: u32>(
+    deserializer: D,
+) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = u32::deserialize(deserializer)?;
+    if !(MIN..=MAX).contains(&value) {
+        return Err(de::Error::custom(format!(
+            "{} is not in {}..={}",
+            value, MIN, MAX
+        )));
+    }
+    Ok(Some(value))
+}
+
+// A theme by name or a list of colours
+pub(crate) fn palette<'de, D>(deserializer: D) -> Result<Option<Palette>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Setting {
+        Name(String),
+        Colors(Vec<String>),
+    }
+
+    let palette = match Setting::deserialize(deserializer)? {
+        Setting::Name(name) => name.parse(),
+        Setting::Colors(colors) => {
+            let colors: Vec<&str> = colors.iter().map(String::as_str).collect();
+            Palette::default().with_colors(&colors)
+        }
+    };
+    palette.map(Some).map_err(de::Error::custom)
+}
+
+fn color<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let text = String::deserialize(deserializer)?;
+    parse_color(&text).map(Some).map_err(de::Error::custom)
+}
+
+// The names of the quirks that are set, like in movies
+pub(crate) fn quirks<'de, D>(deserializer: D) -> Result<Option<Quirks>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut quirks = Quirks::default();
+    for name in Vec::<String>::deserialize(deserializer)? {
+        *quirks
+            .flag_mut(&name)
+            .ok_or_else(|| de::Error::custom(format!("unknown quirk '{}'", name)))? = true;
+    }
+    Ok(Some(quirks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    #[test]
+    fn default_keymap_is_the_vip_layout() {
+        let keymap = Config::default().keymap(ROM, Keymap::default()).unwrap();
+        assert_eq!(keymap.key("1"), Some(0x1));
+        assert_eq!(keymap.key("4"), Some(0xC));
+        assert_eq!(keymap.key("q"), Some(0x4));
+        assert_eq!(keymap.key("X"), Some(0x0));
+        assert_eq!(keymap.key("V"), Some(0xF));
+        assert_eq!(keymap.key("0"), None);
+        assert_eq!(keymap.key("Left"), None);
+    }
+
+    #[test]
+    fn rom_keys_apply_on_top_of_global_keys() {
+        let config = Config::parse(&format!(
+            "[keys]\n5 = [\"Up\", \"W\"]\n\n[roms.{}.keys]\n5 = \"K\"\n",
+            ROM.to_uppercase()
+        ))
+        .unwrap();
+
+        let keymap = config
+            .keymap(
+                "ffffffffffffffffffffffffffffffffffffffff",
+                Keymap::default(),
+            )
+            .unwrap();
+        assert_eq!(keymap.key("up"), Some(0x5));
+        assert_eq!(keymap.key("W"), Some(0x5));
+
+        let keymap = config.keymap(ROM, Keymap::default()).unwrap();
+        assert_eq!(keymap.key("K"), Some(0x5));
+        assert_eq!(keymap.key("Up"), None);
+        assert_eq!(keymap.key("W"), None);
+        assert_eq!(keymap.key("Q"), Some(0x4));
+    }
+
+    #[test]
+    fn invalid_keypad_key_is_an_error() {
+        let config = Config::parse("[keys]\n10 = \"K\"\n").unwrap();
+        assert!(matches!(
+            config.keymap(ROM, Keymap::default()),
+            Err(ConfigError::InvalidKey(key)) if key == "10"
+        ));
+    }
+
+    #[test]
+    fn rom_settings_override_global_settings() {
+        let config = Config::parse(&format!(
+            "platform = \"chip8\"\nipf = 15\nscale = 8\n\n[audio]\nvolume = 0.5\n\n\
+             [roms.{}]\nplatform = \"schip\"\nipf = 30\n\n[roms.{}.audio]\nmute = true\n",
+            ROM, ROM
+        ))
+        .unwrap();
+
+        let settings = config.settings(
+            "ffffffffffffffffffffffffffffffffffffffff",
+            Settings::default(),
+        );
+        assert_eq!(settings.platform, Some(Platform::Chip8));
+        assert_eq!(settings.ipf, Some(15));
+        assert_eq!(settings.mute, None);
+
+        let known = Settings {
+            platform: Some(Platform::XoChip),
+            timing: Some(Timing::CosmacVip),
+            ..Settings::default()
+        };
+        let settings = config.settings(ROM, known);
+        assert_eq!(settings.platform, Some(Platform::SuperChip));
+        assert_eq!(settings.ipf, Some(30));
+        assert_eq!(settings.scale, Some(8));
+        assert_eq!(settings.mute, Some(true));
+        assert_eq!(settings.volume, Some(0.5));
+        assert_eq!(settings.timing, Some(Timing::CosmacVip));
+    }
+
+    #[test]
+    fn invalid_settings_are_errors() {
+        assert!(Config::parse("platform = \"nes\"\n").is_err());
+        assert!(Config::parse("scale = 0\n").is_err());
+        assert!(Config::parse("[roms.abc]\nipf = 1000000\n").is_err());
+        assert!(Config::parse("speed = 10\n").is_err());
+        assert!(Config::parse("quirks = [\"shift\", \"warp\"]\n").is_err());
+    }
+
+    #[test]
+    fn palette_is_a_theme_or_colours() {
+        let config = Config::parse(&format!(
+            "palette = \"octo\"\ngrid = 1\n\n[roms.{}]\n\
+             palette = [\"#102030\", \"FFFFFF\", \"ff0000\", \"00FF00\"]\nforeground = \"#123456\"\n",
+            ROM
+        ))
+        .unwrap();
+
+        let settings = config.settings(&"f".repeat(40), Settings::default());
+        assert_eq!(settings.palette, Some(Palette::OCTO));
+        assert_eq!(settings.grid, Some(1));
+
+        let settings = config.settings(ROM, Settings::default());
+        assert_eq!(
+            settings.palette.map(|palette| palette.colors),
+            Some([0x102030, 0xFFFFFF, 0xFF0000, 0x00FF00])
+        );
+        assert_eq!(settings.foreground, Some(0x123456));
+
+        assert!(Config::parse("palette = \"sepia\"\n").is_err());
+        assert!(Config::parse("palette = [\"0\", \"1\", \"2\", \"3\", \"4\"]\n").is_err());
+        assert!(Config::parse("background = \"#12345\"\n").is_err());
+    }
+}
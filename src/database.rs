@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::{
+    config::{ConfigError, MAX_INSTRUCTIONS_PER_FRAME, Settings, palette, parse, quirks, ranged},
+    emulator::{
+        chip8_context::MEMORY_SIZE,
+        disassembler::trace,
+        emulator::ROM_OFFSET,
+        quirks::{Platform, Quirks},
+    },
+    frontend::{keymap::Keymap, palette::Palette},
+};
+
+// The database built into the emulator, see the file for its format
+const DATABASE: &str = include_str!("database.toml");
+
+// ROMs by their SHA-1, with the settings they need to run properly
+#[derive(Debug, Default)]
+pub struct RomDatabase {
+    roms: HashMap<String, RomInfo>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RomInfo {
+    pub title: String,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    #[serde(default, deserialize_with = "parse")]
+    pub platform: Option<Platform>,
+    #[serde(default, deserialize_with = "quirks")]
+    pub quirks: Option<Quirks>,
+    #[serde(
+        default,
+        deserialize_with = "ranged::<_, 1, MAX_INSTRUCTIONS_PER_FRAME>"
+    )]
+    pub ipf: Option<u32>,
+    #[serde(default, deserialize_with = "palette")]
+    pub colors: Option<Palette>,
+    #[serde(default)]
+    pub keys: GameKeys,
+}
+
+// The keypad keys a game uses to move and act
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GameKeys {
+    pub up: Option<u8>,
+    pub down: Option<u8>,
+    pub left: Option<u8>,
+    pub right: Option<u8>,
+    pub a: Option<u8>,
+    pub b: Option<u8>,
+}
+
+impl RomDatabase {
+    // The database built into the emulator
+    pub fn builtin() -> Self {
+        // The built in file is checked by the tests
+        RomDatabase::parse(DATABASE).expect("built in ROM database is valid")
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let roms: HashMap<String, RomInfo> = toml::from_str(text).map_err(ConfigError::Parse)?;
+        Ok(RomDatabase {
+            roms: roms
+                .into_iter()
+                .map(|(id, rom)| (id.to_ascii_lowercase(), rom))
+                .collect(),
+        })
+    }
+
+    // A ROM by its SHA-1 in hex
+    pub fn lookup(&self, rom_id: &str) -> Option<&RomInfo> {
+        self.roms.get(&rom_id.to_ascii_lowercase())
+    }
+}
+
+impl RomInfo {
+    pub fn settings(&self) -> Settings {
+        Settings {
+            platform: self.platform,
+            quirks: self.quirks,
+            ipf: self.ipf,
+            palette: self.colors,
+            ..Settings::default()
+        }
+    }
+
+    // Binds the arrow keys, the game controller's D-pad and left stick and its A and B
+    // buttons to the keys the game uses
+    pub fn bind_keys(&self, keymap: &mut Keymap) {
+        let keys = self.keys;
+        let bindings: [(Option<u8>, &[&str]); 6] = [
+            (keys.up, &["Up", "pad:dpup", "pad:lefty-"]),
+            (keys.down, &["Down", "pad:dpdown", "pad:lefty+"]),
+            (keys.left, &["Left", "pad:dpleft", "pad:leftx-"]),
+            (keys.right, &["Right", "pad:dpright", "pad:leftx+"]),
+            (keys.a, &["pad:a"]),
+            (keys.b, &["pad:b"]),
+        ];
+        for (key, names) in bindings {
+            let Some(key) = key else { continue };
+            for name in names {
+                keymap.bind(name, key);
+            }
+        }
+    }
+}
+
+// Guesses the platform of a ROM that is not in the database from the instructions its code
+// reaches. Only SUPER-CHIP and XO-CHIP can be told apart this way, None means the ROM looks
+// like plain CHIP-8, which runs the same on all of them.
+pub fn detect_platform(rom: &[u8]) -> Option<Platform> {
+    // Only XO-CHIP has the memory for bigger ROMs
+    if rom.len() > MEMORY_SIZE - ROM_OFFSET {
+        return Some(Platform::XoChip);
+    }
+
+    let platforms: Vec<Platform> = trace(rom, ROM_OFFSET)
+        .values()
+        .map(|instruction| instruction.platform())
+        .collect();
+    [Platform::XoChip, Platform::SuperChip]
+        .into_iter()
+        .find(|platform| platforms.contains(platform))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::emulator::{
+        assembler::{assemble, assemble_file},
+        emulator::rom_id,
+    };
+
+    #[test]
+    fn builtin_database_is_valid() {
+        RomDatabase::builtin();
+    }
+
+    // The test ROMs are known by the hash of what they assemble to
+    #[test]
+    fn test_roms_are_in_builtin_database() {
+        let database = RomDatabase::builtin();
+        let lookup = |name: &str| {
+            let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/roms")
+                .join(format!("{}.asm", name));
+            let id = rom_id(&assemble_file(&path).unwrap());
+            database
+                .lookup(&id)
+                .unwrap_or_else(|| panic!("{} ({}) is not in the database", name, id))
+        };
+
+        for name in ["ibm", "opcodes", "flags", "quirks"] {
+            assert!(lookup(name).title.ends_with("(test ROM)"));
+        }
+
+        let keypad = lookup("keypad");
+        assert_eq!(keypad.title, "Keypad Test (test ROM)");
+        let mut keymap = Keymap::default();
+        keypad.bind_keys(&mut keymap);
+        assert_eq!(keymap.key("pad:a"), Some(0xA));
+        assert_eq!(keymap.key("pad:b"), Some(0x5));
+    }
+
+    #[test]
+    fn rom_is_found_by_hash() {
+        let id = rom_id(&assemble("CLS").unwrap());
+        let database = RomDatabase::parse(&format!(
+            "[{}]\ntitle = \"Game\"\nplatform = \"schip\"\nquirks = [\"shift\"]\nipf = 30\n\
+             colors = [\"#996600\", \"FFCC00\"]\nkeys = {{ up = 5, a = 6 }}\n",
+            id.to_uppercase()
+        ))
+        .unwrap();
+
+        let rom = database.lookup(&id).unwrap();
+        assert_eq!(rom.title, "Game");
+        let settings = rom.settings();
+        assert_eq!(settings.platform, Some(Platform::SuperChip));
+        assert_eq!(settings.quirks.map(|quirks| quirks.shift), Some(true));
+        assert_eq!(settings.ipf, Some(30));
+        assert_eq!(
+            settings.palette.map(|palette| palette.colors),
+            Some([0x996600, 0xFFCC00, 0xAAAAAA, 0x555555])
+        );
+
+        let mut keymap = Keymap::default();
+        rom.bind_keys(&mut keymap);
+        assert_eq!(keymap.key("Up"), Some(0x5));
+        assert_eq!(keymap.key("pad:a"), Some(0x6));
+        assert_eq!(keymap.key("W"), Some(0x5));
+
+        assert!(database.lookup(&"f".repeat(40)).is_none());
+    }
+
+    #[test]
+    fn invalid_entries_are_errors() {
+        assert!(RomDatabase::parse("[abc]\nplatform = \"schip\"\n").is_err());
+        assert!(RomDatabase::parse("[abc]\ntitle = \"A\"\ncolors = [\"red\"]\n").is_err());
+        assert!(RomDatabase::parse("[abc]\ntitle = \"A\"\nkeys = { fire = 5 }\n").is_err());
+    }
+
+    #[test]
+    fn platform_is_detected_from_reachable_code() {
+        let detect = |source: &str| detect_platform(&assemble(source).unwrap());
+
+        assert_eq!(detect("CLS\nDRW V0, V1, 5\nend:\nJP end"), None);
+        assert_eq!(detect("HIGH\nend:\nJP end"), Some(Platform::SuperChip));
+        assert_eq!(
+            detect("SE V0, 1\nCALL sub\nend:\nJP end\nsub:\nLD I, LONG 0x1234\nRET"),
+            Some(Platform::XoChip)
+        );
+        // Sprite data that happens to decode as 00FF is never run
+        assert_eq!(detect("end:\nJP end\nDB 0x00, 0xFF"), None);
+    }
+}
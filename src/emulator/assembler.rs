@@ -0,0 +1,926 @@
+use std::{collections::HashMap, error::Error, fmt, fs, path::Path, rc::Rc};
+
+use super::{decoder::Instruction, emulator::ROM_OFFSET};
+
+// Includes nested deeper than this are assumed to be recursive
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+// Assembles source into a ROM loaded at ROM_OFFSET. Includes are resolved relative to the
+// current directory.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut assembler = Assembler::new();
+    assembler.load(source, None, Path::new("."), 0)?;
+    assembler.assemble()
+}
+
+// Assembles a file, includes are resolved relative to the including file
+pub fn assemble_file(path: &Path) -> Result<Vec<u8>, AsmError> {
+    let source = fs::read_to_string(path).map_err(|e| AsmError {
+        file: None,
+        line: 0,
+        column: 0,
+        message: format!("Could not read {}: {}", path.display(), e),
+    })?;
+    let mut assembler = Assembler::new();
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let name: Rc<str> = path.display().to_string().into();
+    assembler.load(&source, Some(name), dir, 0)?;
+    assembler.assemble()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: Option<Rc<str>>,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(
+                f,
+                "{}:{}:{}: {}",
+                file, self.line, self.column, self.message
+            ),
+            None => write!(
+                f,
+                "line {}, column {}: {}",
+                self.line, self.column, self.message
+            ),
+        }
+    }
+}
+
+impl Error for AsmError {}
+
+#[derive(Debug, Clone)]
+struct Location {
+    file: Option<Rc<str>>,
+    line: usize,
+}
+
+impl Location {
+    fn error(&self, column: usize, message: impl Into<String>) -> AsmError {
+        AsmError {
+            file: self.file.clone(),
+            line: self.line,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    Number(i64),
+    Str(String),
+    Symbol(&'static str),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    column: usize,
+}
+
+const SYMBOLS: [&str; 17] = [
+    "<<", ">>", ",", ":", "=", "(", ")", "[", "]", "+", "-", "*", "/", "%", "&", "|", "^",
+];
+
+fn lex(text: &str, location: &Location) -> Result<Vec<Token>, AsmError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        let c = chars[pos];
+        let column = pos + 1;
+
+        if c == ';' {
+            break;
+        } else if c.is_whitespace() {
+            pos += 1;
+        } else if c == '"' {
+            let end = chars[pos + 1..]
+                .iter()
+                .position(|&c| c == '"')
+                .ok_or_else(|| location.error(column, "Unterminated string"))?;
+            let string = chars[pos + 1..pos + 1 + end].iter().collect();
+            tokens.push(Token {
+                kind: TokenKind::Str(string),
+                column,
+            });
+            pos += end + 2;
+        } else if c.is_ascii_digit() {
+            let len = chars[pos..]
+                .iter()
+                .position(|c| !c.is_ascii_alphanumeric() && *c != '_')
+                .unwrap_or(chars.len() - pos);
+            let literal: String = chars[pos..pos + len]
+                .iter()
+                .filter(|&&c| c != '_')
+                .collect();
+            let lower = literal.to_ascii_lowercase();
+            let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+                i64::from_str_radix(hex, 16)
+            } else if let Some(bin) = lower.strip_prefix("0b") {
+                i64::from_str_radix(bin, 2)
+            } else {
+                lower.parse()
+            };
+            let value = parsed
+                .map_err(|_| location.error(column, format!("Invalid number '{}'", literal)))?;
+            tokens.push(Token {
+                kind: TokenKind::Number(value),
+                column,
+            });
+            pos += len;
+        } else if c.is_alphabetic() || c == '_' || c == '.' || c == '$' {
+            let len = chars[pos..]
+                .iter()
+                .position(|c| !c.is_alphanumeric() && *c != '_' && *c != '.' && *c != '$')
+                .unwrap_or(chars.len() - pos);
+            tokens.push(Token {
+                kind: TokenKind::Ident(chars[pos..pos + len].iter().collect()),
+                column,
+            });
+            pos += len;
+        } else {
+            let rest: String = chars[pos..(pos + 2).min(chars.len())].iter().collect();
+            let symbol = SYMBOLS
+                .iter()
+                .find(|s| rest.starts_with(**s))
+                .ok_or_else(|| location.error(column, format!("Unexpected character '{}'", c)))?;
+            tokens.push(Token {
+                kind: TokenKind::Symbol(symbol),
+                column,
+            });
+            pos += symbol.len();
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(i64),
+    // Name and column
+    Symbol(String, usize),
+    // Address of the current statement
+    Here,
+    // Column of the minus sign and the operand
+    Negate(usize, Box<Expr>),
+    // Operator, its column and the operands
+    Binary(&'static str, usize, Box<Expr>, Box<Expr>),
+}
+
+// Precedence climbing over the binary operators, loosest first
+const PRECEDENCE: [&[&str]; 6] = [
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+struct ExprParser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    location: &'a Location,
+    end_column: usize,
+}
+
+impl ExprParser<'_> {
+    fn column(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map(|t| t.column)
+            .unwrap_or(self.end_column)
+    }
+
+    fn parse(&mut self, level: usize) -> Result<Expr, AsmError> {
+        if level == PRECEDENCE.len() {
+            return self.parse_unary();
+        }
+
+        let mut left = self.parse(level + 1)?;
+        while let Some(Token {
+            kind: TokenKind::Symbol(op),
+            column,
+        }) = self.tokens.get(self.pos)
+        {
+            if !PRECEDENCE[level].contains(op) {
+                break;
+            }
+            self.pos += 1;
+            let right = self.parse(level + 1)?;
+            left = Expr::Binary(op, *column, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, AsmError> {
+        let column = self.column();
+        let token = self
+            .tokens
+            .get(self.pos)
+            .ok_or_else(|| self.location.error(column, "Expected an expression"))?;
+        self.pos += 1;
+
+        match &token.kind {
+            TokenKind::Number(value) => Ok(Expr::Number(*value)),
+            TokenKind::Ident(name) if name == "$" => Ok(Expr::Here),
+            TokenKind::Ident(name) => Ok(Expr::Symbol(name.clone(), token.column)),
+            TokenKind::Symbol("-") => Ok(Expr::Negate(column, Box::new(self.parse_unary()?))),
+            TokenKind::Symbol("(") => {
+                let inner = self.parse(0)?;
+                match self.tokens.get(self.pos) {
+                    Some(Token {
+                        kind: TokenKind::Symbol(")"),
+                        ..
+                    }) => {
+                        self.pos += 1;
+                        Ok(inner)
+                    }
+                    _ => Err(self.location.error(self.column(), "Expected ')'")),
+                }
+            }
+            _ => Err(self.location.error(token.column, "Expected an expression")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Register(u8),
+    I,
+    IndirectI,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    BigFont,
+    Bcd,
+    Flags,
+    Long(Expr),
+    Value(Expr),
+    Str(String),
+}
+
+#[derive(Debug, Clone)]
+struct Argument {
+    operand: Operand,
+    column: usize,
+}
+
+fn register(name: &str) -> Option<u8> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v' | 'V'), Some(digit), None) => digit.to_digit(16).map(|d| d as u8),
+        _ => None,
+    }
+}
+
+fn is_reserved(name: &str) -> bool {
+    register(name).is_some()
+        || matches!(
+            name.to_ascii_uppercase().as_str(),
+            "I" | "DT" | "ST" | "K" | "F" | "HF" | "B" | "R" | "LONG" | "EQU"
+        )
+}
+
+fn parse_argument(
+    tokens: &[Token],
+    location: &Location,
+    end_column: usize,
+) -> Result<Argument, AsmError> {
+    let column = tokens.first().map(|t| t.column).unwrap_or(end_column);
+    let keyword = |operand: Operand| Ok(Argument { operand, column });
+
+    match tokens {
+        [] => Err(location.error(column, "Expected an operand")),
+        [
+            Token {
+                kind: TokenKind::Symbol("["),
+                ..
+            },
+            Token {
+                kind: TokenKind::Ident(i),
+                ..
+            },
+            Token {
+                kind: TokenKind::Symbol("]"),
+                ..
+            },
+        ] if i.eq_ignore_ascii_case("i") => keyword(Operand::IndirectI),
+        [
+            Token {
+                kind: TokenKind::Str(string),
+                ..
+            },
+        ] => keyword(Operand::Str(string.clone())),
+        [
+            Token {
+                kind: TokenKind::Ident(name),
+                ..
+            },
+        ] if is_reserved(name) => match name.to_ascii_uppercase().as_str() {
+            "I" => keyword(Operand::I),
+            "DT" => keyword(Operand::DelayTimer),
+            "ST" => keyword(Operand::SoundTimer),
+            "K" => keyword(Operand::Key),
+            "F" => keyword(Operand::Font),
+            "HF" => keyword(Operand::BigFont),
+            "B" => keyword(Operand::Bcd),
+            "R" => keyword(Operand::Flags),
+            _ => match register(name) {
+                Some(x) => keyword(Operand::Register(x)),
+                None => Err(location.error(column, format!("Unexpected '{}'", name))),
+            },
+        },
+        [
+            Token {
+                kind: TokenKind::Ident(long),
+                ..
+            },
+            rest @ ..,
+        ] if long.eq_ignore_ascii_case("long") => {
+            let expr = parse_expr(rest, location, end_column)?;
+            keyword(Operand::Long(expr))
+        }
+        _ => {
+            let expr = parse_expr(tokens, location, end_column)?;
+            keyword(Operand::Value(expr))
+        }
+    }
+}
+
+fn parse_expr(tokens: &[Token], location: &Location, end_column: usize) -> Result<Expr, AsmError> {
+    let mut parser = ExprParser {
+        tokens,
+        pos: 0,
+        location,
+        end_column,
+    };
+    let expr = parser.parse(0)?;
+    if parser.pos < tokens.len() {
+        return Err(location.error(parser.column(), "Unexpected token in expression"));
+    }
+    Ok(expr)
+}
+
+// Splits operands on commas that are not nested in brackets or parentheses
+fn split_arguments(tokens: &[Token]) -> Vec<&[Token]> {
+    let mut arguments = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (index, token) in tokens.iter().enumerate() {
+        match token.kind {
+            TokenKind::Symbol("(" | "[") => depth += 1,
+            TokenKind::Symbol(")" | "]") => depth -= 1,
+            TokenKind::Symbol(",") if depth == 0 => {
+                arguments.push(&tokens[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    if start < tokens.len() || !arguments.is_empty() {
+        arguments.push(&tokens[start..]);
+    }
+    arguments
+}
+
+#[derive(Debug)]
+enum StatementKind {
+    Instruction {
+        mnemonic: String,
+        column: usize,
+        arguments: Vec<Argument>,
+    },
+    Bytes(Vec<Argument>),
+    Words(Vec<Argument>),
+}
+
+#[derive(Debug)]
+struct Statement {
+    location: Location,
+    address: usize,
+    kind: StatementKind,
+}
+
+#[derive(Debug, Clone)]
+enum Symbol {
+    Label(usize),
+    Constant(Expr, Location),
+}
+
+struct Assembler {
+    statements: Vec<Statement>,
+    symbols: HashMap<String, Symbol>,
+    address: usize,
+}
+
+impl Assembler {
+    fn new() -> Self {
+        Assembler {
+            statements: Vec::new(),
+            symbols: HashMap::new(),
+            address: ROM_OFFSET,
+        }
+    }
+
+    // First pass: parses every line, follows includes and assigns addresses to labels
+    fn load(
+        &mut self,
+        source: &str,
+        file: Option<Rc<str>>,
+        dir: &Path,
+        depth: usize,
+    ) -> Result<(), AsmError> {
+        for (index, text) in source.lines().enumerate() {
+            let location = Location {
+                file: file.clone(),
+                line: index + 1,
+            };
+            let end_column = text.chars().count() + 1;
+            let mut tokens = &lex(text, &location)?[..];
+
+            // Labels
+            while let [
+                Token {
+                    kind: TokenKind::Ident(name),
+                    column,
+                },
+                Token {
+                    kind: TokenKind::Symbol(":"),
+                    ..
+                },
+                rest @ ..,
+            ] = tokens
+            {
+                self.define(name, *column, Symbol::Label(self.address), &location)?;
+                tokens = rest;
+            }
+
+            let Some(first) = tokens.first() else {
+                continue;
+            };
+            let TokenKind::Ident(name) = &first.kind else {
+                return Err(location.error(first.column, "Expected a mnemonic or directive"));
+            };
+
+            // Constants, NAME = expr or NAME EQU expr
+            if let Some(Token { kind, .. }) = tokens.get(1)
+                && (*kind == TokenKind::Symbol("=")
+                    || matches!(kind, TokenKind::Ident(equ) if equ.eq_ignore_ascii_case("equ")))
+            {
+                let expr = parse_expr(&tokens[2..], &location, end_column)?;
+                self.define(
+                    name,
+                    first.column,
+                    Symbol::Constant(expr, location.clone()),
+                    &location,
+                )?;
+                continue;
+            }
+
+            let mnemonic = name.to_ascii_uppercase();
+            let arguments = split_arguments(&tokens[1..])
+                .into_iter()
+                .map(|tokens| parse_argument(tokens, &location, end_column))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let (kind, size) = match mnemonic.as_str() {
+                "INCLUDE" => {
+                    let [
+                        Argument {
+                            operand: Operand::Str(path),
+                            column,
+                        },
+                    ] = &arguments[..]
+                    else {
+                        return Err(location.error(first.column, "Expected INCLUDE \"file\""));
+                    };
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(location.error(*column, "Includes nested too deeply"));
+                    }
+                    let path = dir.join(path);
+                    let source = fs::read_to_string(&path).map_err(|e| {
+                        location.error(*column, format!("Could not read {}: {}", path.display(), e))
+                    })?;
+                    let include_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+                    let name: Rc<str> = path.display().to_string().into();
+                    self.load(&source, Some(name), &include_dir, depth + 1)?;
+                    continue;
+                }
+                "DB" => {
+                    let size = arguments
+                        .iter()
+                        .map(|argument| match &argument.operand {
+                            Operand::Str(string) => string.len(),
+                            _ => 1,
+                        })
+                        .sum();
+                    (StatementKind::Bytes(arguments), size)
+                }
+                "DW" => {
+                    let size = arguments.len() * 2;
+                    (StatementKind::Words(arguments), size)
+                }
+                _ => {
+                    let long = matches!(
+                        arguments.get(1),
+                        Some(Argument {
+                            operand: Operand::Long(_),
+                            ..
+                        })
+                    );
+                    let kind = StatementKind::Instruction {
+                        mnemonic,
+                        column: first.column,
+                        arguments,
+                    };
+                    (kind, if long { 4 } else { 2 })
+                }
+            };
+
+            self.statements.push(Statement {
+                location,
+                address: self.address,
+                kind,
+            });
+            self.address += size;
+        }
+
+        Ok(())
+    }
+
+    fn define(
+        &mut self,
+        name: &str,
+        column: usize,
+        symbol: Symbol,
+        location: &Location,
+    ) -> Result<(), AsmError> {
+        if is_reserved(name) {
+            return Err(location.error(column, format!("'{}' is a reserved name", name)));
+        }
+        if self.symbols.insert(name.to_string(), symbol).is_some() {
+            return Err(location.error(column, format!("'{}' is already defined", name)));
+        }
+        Ok(())
+    }
+
+    // Second pass: evaluates operands and encodes every statement
+    fn assemble(&self) -> Result<Vec<u8>, AsmError> {
+        let mut rom = Vec::new();
+
+        for statement in &self.statements {
+            let location = &statement.location;
+            let eval = |argument: &Argument, min: i64, max: i64| -> Result<i64, AsmError> {
+                let expr = match &argument.operand {
+                    Operand::Value(expr) | Operand::Long(expr) => expr,
+                    _ => return Err(location.error(argument.column, "Expected a value")),
+                };
+                let value = self.evaluate(expr, statement.address, location, 0)?;
+                if value < min || value > max {
+                    return Err(location.error(
+                        argument.column,
+                        format!("Value {} out of range {}..={}", value, min, max),
+                    ));
+                }
+                Ok(value)
+            };
+
+            match &statement.kind {
+                StatementKind::Bytes(arguments) => {
+                    for argument in arguments {
+                        match &argument.operand {
+                            Operand::Str(string) => rom.extend(string.bytes()),
+                            _ => rom.push(eval(argument, -128, 255)? as u8),
+                        }
+                    }
+                }
+                StatementKind::Words(arguments) => {
+                    for argument in arguments {
+                        let value = eval(argument, -32768, 65535)? as u16;
+                        rom.extend(value.to_be_bytes());
+                    }
+                }
+                StatementKind::Instruction {
+                    mnemonic,
+                    column,
+                    arguments,
+                } => {
+                    let instruction =
+                        self.instruction(mnemonic, *column, arguments, location, &eval)?;
+                    rom.extend(instruction.encode());
+                }
+            }
+        }
+
+        Ok(rom)
+    }
+
+    fn instruction(
+        &self,
+        mnemonic: &str,
+        column: usize,
+        arguments: &[Argument],
+        location: &Location,
+        eval: &dyn Fn(&Argument, i64, i64) -> Result<i64, AsmError>,
+    ) -> Result<Instruction, AsmError> {
+        use Operand::*;
+
+        let address = |argument: &Argument| eval(argument, 0, 0xFFF).map(|v| v as u16);
+        let byte = |argument: &Argument| eval(argument, -128, 255).map(|v| v as u8);
+        let nibble = |argument: &Argument| eval(argument, 0, 15).map(|v| v as u8);
+        let invalid = || location.error(column, format!("Invalid operands for {}", mnemonic));
+
+        let operands: Vec<&Operand> = arguments.iter().map(|a| &a.operand).collect();
+        let instruction = match (mnemonic, &operands[..]) {
+            ("SYS", [Value(_)]) => Instruction::Sys(address(&arguments[0])?),
+            ("CLS", []) => Instruction::Cls,
+            ("RET", []) => Instruction::Ret,
+            ("SCD", [Value(_)]) => Instruction::ScrollDown(nibble(&arguments[0])?),
+            ("SCU", [Value(_)]) => Instruction::ScrollUp(nibble(&arguments[0])?),
+            ("SCR", []) => Instruction::ScrollRight,
+            ("SCL", []) => Instruction::ScrollLeft,
+            ("EXIT", []) => Instruction::Exit,
+            ("LOW", []) => Instruction::Lores,
+            ("HIGH", []) => Instruction::Hires,
+            ("JP", [Value(_)]) => Instruction::Jump(address(&arguments[0])?),
+            ("JP", [Register(0), Value(_)]) => Instruction::JumpOffset(address(&arguments[1])?),
+            ("CALL", [Value(_)]) => Instruction::Call(address(&arguments[0])?),
+            ("SE", [Register(x), Register(y)]) => Instruction::SkipEq(*x, *y),
+            ("SE", [Register(x), Value(_)]) => Instruction::SkipEqImm(*x, byte(&arguments[1])?),
+            ("SNE", [Register(x), Register(y)]) => Instruction::SkipNe(*x, *y),
+            ("SNE", [Register(x), Value(_)]) => Instruction::SkipNeImm(*x, byte(&arguments[1])?),
+            ("SAVE", [Register(x), Register(y)]) => Instruction::SaveRange(*x, *y),
+            ("LOAD", [Register(x), Register(y)]) => Instruction::LoadRange(*x, *y),
+            ("LD", [Register(x), Register(y)]) => Instruction::Move(*x, *y),
+            ("LD", [Register(x), Value(_)]) => Instruction::LoadImm(*x, byte(&arguments[1])?),
+            ("LD", [I, Value(_)]) => Instruction::LoadI(address(&arguments[1])?),
+            ("LD", [I, Long(_)]) => Instruction::LoadLongI(eval(&arguments[1], 0, 0xFFFF)? as u16),
+            ("LD", [Register(x), DelayTimer]) => Instruction::LoadDelay(*x),
+            ("LD", [Register(x), Key]) => Instruction::WaitKey(*x),
+            ("LD", [DelayTimer, Register(x)]) => Instruction::SetDelay(*x),
+            ("LD", [SoundTimer, Register(x)]) => Instruction::SetSound(*x),
+            ("LD", [Font, Register(x)]) => Instruction::Font(*x),
+            ("LD", [BigFont, Register(x)]) => Instruction::BigFont(*x),
+            ("LD", [Bcd, Register(x)]) => Instruction::Bcd(*x),
+            ("LD", [IndirectI, Register(x)]) => Instruction::Store(*x),
+            ("LD", [Register(x), IndirectI]) => Instruction::Load(*x),
+            ("LD", [Flags, Register(x)]) => Instruction::SaveFlags(*x),
+            ("LD", [Register(x), Flags]) => Instruction::LoadFlags(*x),
+            ("ADD", [Register(x), Register(y)]) => Instruction::Add(*x, *y),
+            ("ADD", [Register(x), Value(_)]) => Instruction::AddImm(*x, byte(&arguments[1])?),
+            ("ADD", [I, Register(x)]) => Instruction::AddI(*x),
+            ("OR", [Register(x), Register(y)]) => Instruction::Or(*x, *y),
+            ("AND", [Register(x), Register(y)]) => Instruction::And(*x, *y),
+            ("XOR", [Register(x), Register(y)]) => Instruction::Xor(*x, *y),
+            ("SUB", [Register(x), Register(y)]) => Instruction::Sub(*x, *y),
+            ("SUBN", [Register(x), Register(y)]) => Instruction::SubReverse(*x, *y),
+            // Without VY, shift VX itself so the result does not depend on the shift quirk
+            ("SHR", [Register(x)]) => Instruction::ShiftRight(*x, *x),
+            ("SHR", [Register(x), Register(y)]) => Instruction::ShiftRight(*x, *y),
+            ("SHL", [Register(x)]) => Instruction::ShiftLeft(*x, *x),
+            ("SHL", [Register(x), Register(y)]) => Instruction::ShiftLeft(*x, *y),
+            ("RND", [Register(x), Value(_)]) => Instruction::Random(*x, byte(&arguments[1])?),
+            ("DRW", [Register(x), Register(y), Value(_)]) => {
+                Instruction::Draw(*x, *y, nibble(&arguments[2])?)
+            }
+            ("SKP", [Register(x)]) => Instruction::SkipKey(*x),
+            ("SKNP", [Register(x)]) => Instruction::SkipNotKey(*x),
+            // Any nibble, as FN01 decodes for every N though only its low two bits select planes
+            ("PLANE", [Value(_)]) => Instruction::Plane(nibble(&arguments[0])?),
+            ("AUDIO", []) => Instruction::Audio,
+            ("PITCH", [Register(x)]) => Instruction::Pitch(*x),
+            (
+                "SYS" | "CLS" | "RET" | "SCD" | "SCU" | "SCR" | "SCL" | "EXIT" | "LOW" | "HIGH"
+                | "JP" | "CALL" | "SE" | "SNE" | "SAVE" | "LOAD" | "LD" | "ADD" | "OR" | "AND"
+                | "XOR" | "SUB" | "SUBN" | "SHR" | "SHL" | "RND" | "DRW" | "SKP" | "SKNP" | "PLANE"
+                | "AUDIO" | "PITCH",
+                _,
+            ) => return Err(invalid()),
+            _ => {
+                return Err(location.error(column, format!("Unknown mnemonic '{}'", mnemonic)));
+            }
+        };
+
+        Ok(instruction)
+    }
+
+    fn evaluate(
+        &self,
+        expr: &Expr,
+        here: usize,
+        location: &Location,
+        depth: usize,
+    ) -> Result<i64, AsmError> {
+        match expr {
+            Expr::Number(value) => Ok(*value),
+            Expr::Here => Ok(here as i64),
+            Expr::Negate(column, inner) => {
+                let value = self.evaluate(inner, here, location, depth)?;
+                value.checked_neg().ok_or_else(|| {
+                    location.error(*column, format!("Invalid arithmetic: -({})", value))
+                })
+            }
+            Expr::Symbol(name, column) => match self.symbols.get(name) {
+                Some(Symbol::Label(address)) => Ok(*address as i64),
+                Some(Symbol::Constant(expr, defined_at)) => {
+                    if depth > self.symbols.len() {
+                        return Err(location
+                            .error(*column, format!("'{}' is defined in terms of itself", name)));
+                    }
+                    self.evaluate(expr, here, defined_at, depth + 1)
+                }
+                None => Err(location.error(*column, format!("Undefined symbol '{}'", name))),
+            },
+            Expr::Binary(op, column, left, right) => {
+                let left = self.evaluate(left, here, location, depth)?;
+                let right = self.evaluate(right, here, location, depth)?;
+                let value = match *op {
+                    "+" => left.checked_add(right),
+                    "-" => left.checked_sub(right),
+                    "*" => left.checked_mul(right),
+                    "/" => left.checked_div(right),
+                    "%" => left.checked_rem(right),
+                    "<<" => u32::try_from(right).ok().and_then(|r| left.checked_shl(r)),
+                    ">>" => u32::try_from(right).ok().and_then(|r| left.checked_shr(r)),
+                    "&" => Some(left & right),
+                    "|" => Some(left | right),
+                    "^" => Some(left ^ right),
+                    _ => unreachable!("Unknown operator {}", op),
+                };
+                value.ok_or_else(|| {
+                    location.error(
+                        *column,
+                        format!("Invalid arithmetic: {} {} {}", left, op, right),
+                    )
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::disassembler::disassemble;
+
+    fn words(source: &str) -> Vec<u16> {
+        assemble(source)
+            .unwrap()
+            .chunks(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect()
+    }
+
+    fn error(source: &str) -> String {
+        assemble(source).unwrap_err().message
+    }
+
+    #[test]
+    fn every_mnemonic_encodes() {
+        let cases: &[(&str, &[u16])] = &[
+            ("SYS 0x123", &[0x0123]),
+            ("CLS", &[0x00E0]),
+            ("RET", &[0x00EE]),
+            ("SCD 4", &[0x00C4]),
+            ("SCU 3", &[0x00D3]),
+            ("SCR", &[0x00FB]),
+            ("SCL", &[0x00FC]),
+            ("EXIT", &[0x00FD]),
+            ("LOW", &[0x00FE]),
+            ("HIGH", &[0x00FF]),
+            ("JP 0x345", &[0x1345]),
+            ("JP V0, 0x345", &[0xB345]),
+            ("CALL 0x456", &[0x2456]),
+            ("SE V1, 0x22", &[0x3122]),
+            ("SE V1, V2", &[0x5120]),
+            ("SNE V1, 0x22", &[0x4122]),
+            ("SNE V1, V2", &[0x9120]),
+            ("SAVE V1, V2", &[0x5122]),
+            ("LOAD V1, V2", &[0x5123]),
+            ("LD V3, 0x44", &[0x6344]),
+            ("LD V3, V4", &[0x8340]),
+            ("LD I, 0x567", &[0xA567]),
+            ("LD I, LONG 0x1234", &[0xF000, 0x1234]),
+            ("LD V5, DT", &[0xF507]),
+            ("LD V5, K", &[0xF50A]),
+            ("LD DT, V5", &[0xF515]),
+            ("LD ST, V5", &[0xF518]),
+            ("LD F, V5", &[0xF529]),
+            ("LD HF, V5", &[0xF530]),
+            ("LD B, V5", &[0xF533]),
+            ("LD [I], V5", &[0xF555]),
+            ("LD V5, [I]", &[0xF565]),
+            ("LD R, V5", &[0xF575]),
+            ("LD V5, R", &[0xF585]),
+            ("ADD V6, 0x07", &[0x7607]),
+            ("ADD V6, V7", &[0x8674]),
+            ("ADD I, V6", &[0xF61E]),
+            ("OR V6, V7", &[0x8671]),
+            ("AND V6, V7", &[0x8672]),
+            ("XOR V6, V7", &[0x8673]),
+            ("SUB V6, V7", &[0x8675]),
+            ("SUBN V6, V7", &[0x8677]),
+            ("SHR V6", &[0x8666]),
+            ("SHR V6, V7", &[0x8676]),
+            ("SHL V6", &[0x866E]),
+            ("SHL V6, V7", &[0x867E]),
+            ("RND V8, 0x0F", &[0xC80F]),
+            ("DRW V8, V9, 15", &[0xD89F]),
+            ("SKP V8", &[0xE89E]),
+            ("SKNP V8", &[0xE8A1]),
+            ("PLANE 3", &[0xF301]),
+            ("AUDIO", &[0xF002]),
+            ("PITCH V8", &[0xF83A]),
+            ("DB 1, 2", &[0x0102]),
+            ("DW 0xBEEF", &[0xBEEF]),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(&words(source), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn mnemonics_and_registers_ignore_case() {
+        assert_eq!(words("ld va, 1\ndrw v0, v1, 2"), [0x6A01, 0xD012]);
+    }
+
+    #[test]
+    fn labels_constants_and_expressions() {
+        let source = "\
+            SPEED = 2 * (3 + 1)
+            DELAY EQU SPEED << 1
+            start:
+                LD V0, SPEED
+                LD V1, DELAY | 1
+                JP end
+            data:
+                DB -1, \"AB\"
+            end:
+                JP $
+                LD I, data + 1";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [
+                0x60, 0x08, 0x61, 0x11, 0x12, 0x09, 0xFF, b'A', b'B', 0x12, 0x09, 0xA2, 0x07
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        assert_eq!(error("LD V0, 256"), "Value 256 out of range -128..=255");
+        assert_eq!(error("JP 0x1000"), "Value 4096 out of range 0..=4095");
+        assert_eq!(error("FOO V0"), "Unknown mnemonic 'FOO'");
+        assert_eq!(error("LD DT, 5"), "Invalid operands for LD");
+        assert_eq!(error("JP nowhere"), "Undefined symbol 'nowhere'");
+        assert_eq!(
+            error("one = two\ntwo = one\nJP one"),
+            "'two' is defined in terms of itself"
+        );
+        assert_eq!(error("x:\nx:"), "'x' is already defined");
+        assert_eq!(error("V1 = 2"), "'V1' is a reserved name");
+        assert_eq!(error("LD V0, 1 / 0"), "Invalid arithmetic: 1 / 0");
+        assert_eq!(
+            error("LD V0, -(0 - 0x7FFFFFFFFFFFFFFF - 1)"),
+            "Invalid arithmetic: -(-9223372036854775808)"
+        );
+        assert_eq!(error("DB \"open"), "Unterminated string");
+
+        let located = assemble("CLS\n  LD V0, 0x1FF").unwrap_err();
+        assert_eq!((located.line, located.column), (2, 10));
+    }
+
+    // Every opcode disassembles to a line that assembles back to the same bytes
+    #[test]
+    fn disassembly_reassembles_for_every_opcode() {
+        for opcode in 0..=u16::MAX {
+            let mut rom = opcode.to_be_bytes().to_vec();
+            // The address word of F000 NNNN
+            if opcode == 0xF000 {
+                rom.extend([0xAB, 0xCD]);
+            }
+            let listing: String = disassemble(&rom, ROM_OFFSET)
+                .iter()
+                .map(|line| line.to_string() + "\n")
+                .collect();
+            match assemble(&listing) {
+                Ok(bytes) => assert_eq!(bytes, rom, "{:04X}: {}", opcode, listing),
+                Err(error) => panic!("{:04X}: {} does not assemble: {}", opcode, listing, error),
+            }
+        }
+    }
+}
@@ -1,17 +1,17 @@
-use std::time::{Duration, Instant};
-
-use sdl2::{pixels::Color, rect::Rect, render::Canvas, video::Window};
-
-pub const LOOP_SPEED: f64 = 1.0 / 700.0;
 pub const TIMER_SPEED: f64 = 1.0 / 60.0;
+// About 700 instructions per second at 60 frames per second
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 11;
 pub const WIDTH: usize = 64;
 pub const HEIGHT: usize = 32;
-pub const SCALE: u32 = 10;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+pub const MEMORY_SIZE: usize = 0x1000;
+pub const XO_MEMORY_SIZE: usize = 0x10000;
 
 #[derive(Debug)]
 pub struct Chip8Context {
-    // RAM
-    pub memory: [u8; 4096],
+    // RAM, 4 KiB or 64 KiB for XO-CHIP
+    pub memory: Vec<u8>,
 
     //  Registers
     pub v: [u8; 16],
@@ -25,20 +25,30 @@ pub struct Chip8Context {
     pub pc: usize,
     pub delay: u8,
     pub sound: u8,
-    last_timer_update: Instant,
+
+    // Set on every timer tick, used to emulate waiting for vertical blank
+    pub vblank: bool,
 
     // Framebuffer
     pub frame_buffer: FrameBuffer,
 
     // Input
     pub held_keys: [bool; 16],
-    pub input: Option<u8>,
+    // The key FX0A saw pressed and waits to be released
+    pub wait_key: Option<u8>,
+
+    // SUPER-CHIP persistent user flags (FX75/FX85)
+    pub rpl: [u8; 16],
+
+    // XO-CHIP audio: 128 bit sample pattern and playback pitch
+    pub audio_pattern: [u8; 16],
+    pub pitch: u8,
 }
 
 impl Chip8Context {
     pub fn new() -> Self {
         Chip8Context {
-            memory: [0; 4096],
+            memory: vec![0; MEMORY_SIZE],
             v: [0; 16],
             stack: [0; 16],
             sp: 0,
@@ -46,54 +56,67 @@ impl Chip8Context {
             pc: 0x200,
             delay: 0,
             sound: 0,
-            last_timer_update: Instant::now(),
+            vblank: false,
             frame_buffer: FrameBuffer::new(),
 
             // Used to check for held keys
             held_keys: [false; 16],
 
-            // Used when program is blocked on input
-            input: None,
+            wait_key: None,
+
+            rpl: [0; 16],
+
+            // A 500 Hz square wave at the default pitch
+            audio_pattern: [0xF0; 16],
+            pitch: 64,
         }
     }
 
-    pub fn get_next_instruction(&self) -> (u8, u8) {
-        (self.memory[self.pc], self.memory[self.pc + 1])
+    // None when the program counter is outside of memory
+    pub fn get_next_instruction(&self) -> Option<(u8, u8)> {
+        Some((*self.memory.get(self.pc)?, *self.memory.get(self.pc + 1)?))
     }
 
     pub fn increment_pc(&mut self) {
         self.pc += 2;
     }
 
+    // Skips the next instruction, which is four bytes long for XO-CHIP's F000 NNNN
+    pub fn skip_instruction(&mut self) {
+        if self.get_next_instruction() == Some((0xF0, 0x00)) {
+            self.pc += 2;
+        }
+        self.pc += 2;
+    }
+
     pub fn decrement_pc(&mut self) {
         self.pc -= 2;
     }
 
-    pub fn stack_push(&mut self, value: u16) {
-        self.stack[self.sp] = value;
+    // None when the stack is full
+    pub fn stack_push(&mut self, value: u16) -> Option<()> {
+        *self.stack.get_mut(self.sp)? = value;
         self.sp += 1;
+        Some(())
     }
 
-    pub fn stack_pop(&mut self) -> u16 {
-        self.sp -= 1;
-        self.stack[self.sp]
+    // None when the stack is empty
+    pub fn stack_pop(&mut self) -> Option<u16> {
+        self.sp = self.sp.checked_sub(1)?;
+        Some(self.stack[self.sp])
     }
 
-    pub fn update_timers(&mut self) {
-        let interval = Duration::from_secs_f64(TIMER_SPEED);
-        let elapsed = self.last_timer_update.elapsed();
-
-        if elapsed >= interval {
-            if self.delay > 0 {
-                self.delay -= 1;
-            }
-
-            if self.sound > 0 {
-                self.sound -= 1;
-            }
+    // One 60 Hz tick of the delay and sound timers
+    pub fn tick_timers(&mut self) {
+        if self.delay > 0 {
+            self.delay -= 1;
+        }
 
-            self.last_timer_update = Instant::now();
+        if self.sound > 0 {
+            self.sound -= 1;
         }
+
+        self.vblank = true;
     }
 }
 
@@ -103,26 +126,57 @@ impl Default for Chip8Context {
     }
 }
 
+// Each pixel holds a bitmask of the XO-CHIP bitplanes it is lit in
 #[derive(Debug)]
 pub struct FrameBuffer {
-    buffer: [bool; WIDTH * HEIGHT],
+    pub(crate) buffer: [u8; HIRES_WIDTH * HIRES_HEIGHT],
+    pub(crate) hires: bool,
+    pub(crate) planes: u8,
     dirty: bool,
 }
 
 impl FrameBuffer {
     pub fn new() -> Self {
         FrameBuffer {
-            buffer: [false; WIDTH * HEIGHT],
+            buffer: [0; HIRES_WIDTH * HIRES_HEIGHT],
+            hires: false,
+            planes: 1,
             dirty: false,
         }
     }
-    pub fn get_pixel(&self, x: usize, y: usize) -> Option<bool> {
-        let index = x * HEIGHT + y;
-        self.buffer.get(index).copied()
+    pub fn width(&self) -> usize {
+        if self.hires { HIRES_WIDTH } else { WIDTH }
+    }
+    pub fn height(&self) -> usize {
+        if self.hires { HIRES_HEIGHT } else { HEIGHT }
     }
-    pub fn set_pixel(&mut self, x: usize, y: usize, value: bool) {
-        let index = x * HEIGHT + y;
-        if let Some(elem) = self.buffer.get_mut(index) {
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+    // Switching resolution clears all planes
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.buffer = [0; HIRES_WIDTH * HIRES_HEIGHT];
+        self.dirty = true;
+    }
+    // Planes affected by drawing, clearing and scrolling
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+    pub fn select_planes(&mut self, planes: u8) {
+        self.planes = planes & 0b11;
+    }
+    pub fn get_pixel(&self, x: usize, y: usize) -> Option<u8> {
+        if x >= self.width() || y >= self.height() {
+            return None;
+        }
+        self.buffer.get(y * HIRES_WIDTH + x).copied()
+    }
+    pub fn set_pixel(&mut self, x: usize, y: usize, value: u8) {
+        if x >= self.width() || y >= self.height() {
+            return;
+        }
+        if let Some(elem) = self.buffer.get_mut(y * HIRES_WIDTH + x) {
             *elem = value;
         }
         self.dirty = true;
@@ -131,30 +185,60 @@ impl FrameBuffer {
         self.dirty
     }
     pub fn clear(&mut self) {
-        self.buffer = [false; WIDTH * HEIGHT];
+        let planes = self.planes;
+        for pixel in self.buffer.iter_mut() {
+            *pixel &= !planes;
+        }
         self.dirty = true;
     }
-    pub fn render(&mut self, canvas: &mut Canvas<Window>) {
-        canvas.set_draw_color(Color::BLACK);
-        canvas.clear();
-        canvas.set_draw_color(Color::WHITE);
-
-        for y in 0..HEIGHT {
-            for x in 0..WIDTH {
-                if self.get_pixel(x, y).expect("Invalid index") {
-                    let rect = Rect::new(
-                        (x as u32 * SCALE) as i32,
-                        (y as u32 * SCALE) as i32,
-                        SCALE,
-                        SCALE,
-                    );
-                    let _ = canvas.fill_rect(rect);
-                }
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.scroll(0, lines as isize);
+    }
+    pub fn scroll_up(&mut self, lines: usize) {
+        self.scroll(0, -(lines as isize));
+    }
+    pub fn scroll_right(&mut self, columns: usize) {
+        self.scroll(columns as isize, 0);
+    }
+    pub fn scroll_left(&mut self, columns: usize) {
+        self.scroll(-(columns as isize), 0);
+    }
+    // Moves the selected planes, pixels scrolled in from outside the screen are unlit
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let planes = self.planes;
+        let source = self.buffer;
+
+        for y in 0..height {
+            for x in 0..width {
+                let (from_x, from_y) = (x - dx, y - dy);
+                let moved = if (0..width).contains(&from_x) && (0..height).contains(&from_y) {
+                    source[(from_y * HIRES_WIDTH as isize + from_x) as usize] & planes
+                } else {
+                    0
+                };
+                let index = (y * HIRES_WIDTH as isize + x) as usize;
+                self.buffer[index] = (source[index] & !planes) | moved;
             }
         }
-        canvas.present();
+        self.dirty = true;
+    }
+    // Pixels of the current resolution in row-major order, as bitplane masks
+    pub fn pixels(&self) -> impl Iterator<Item = u8> + '_ {
+        let width = self.width();
+        self.buffer
+            .chunks(HIRES_WIDTH)
+            .take(self.height())
+            .flat_map(move |row| row[..width].iter().copied())
+    }
+    // Called by the frontend once the current contents have been presented
+    pub fn mark_clean(&mut self) {
         self.dirty = false;
     }
+    // Forces a redraw, for when the contents were replaced wholesale
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
 }
 
 impl Default for FrameBuffer {
@@ -0,0 +1,777 @@
+use std::{collections::BTreeSet, fmt, ops::Range};
+
+use super::{
+    decoder::{Instruction, decode_at},
+    disassembler::disassemble,
+    emulator::{Chip8Emulator, EmulatorMode},
+};
+
+const HELP: &str = "\
+break <addr>                 stop when PC reaches addr
+watch <addr> [len] [r|w|rw]  stop before memory is read or written, writes by default
+cond <reg> <op> <value>      stop when a register comparison becomes true, e.g. cond v3 == 0x10
+delete [addr]                remove the breakpoint at addr, or everything
+info                         list breakpoints, watchpoints and conditions
+regs                         show registers and the call stack
+mem <addr> [len]             dump memory
+disasm [addr] [count]        disassemble, from PC by default
+step, next, finish           step into, over or out of subroutines
+continue, pause              resume or stop running";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    // Whether a watchpoint of this kind catches the given access
+    fn catches(self, access: Access) -> bool {
+        self == Access::ReadWrite || self == access
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: Range<usize>,
+    pub access: Access,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+    Sp,
+    Delay,
+    Sound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+// Why the debugger stopped a running program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    Breakpoint(usize),
+    // The instruction at PC is about to access a watched address
+    Watchpoint { address: usize, access: Access },
+    Condition(Condition),
+    // A step, step over or step out finished
+    Step,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunUntil {
+    Step,
+    // Step over a call, stop once it returned to the same stack depth
+    Return { pc: usize, sp: usize },
+    // Stop after the current subroutine returns
+    StepOut { sp: usize },
+}
+
+#[derive(Debug, Default)]
+pub struct Debugger {
+    pub breakpoints: BTreeSet<usize>,
+    pub watchpoints: Vec<Watchpoint>,
+
+    // Conditions only stop when they become true, so each remembers its last result
+    conditions: Vec<(Condition, bool)>,
+
+    run_until: Option<RunUntil>,
+
+    // Why the program last stopped, None after a pause
+    last_stop: Option<StopReason>,
+
+    // Set when resuming, so the instruction execution stopped at runs instead of stopping again
+    resuming: bool,
+
+    // The last instruction is blocked and runs again, which is not a new stop
+    pub(crate) waiting: bool,
+}
+
+impl Debugger {
+    pub fn last_stop(&self) -> Option<&StopReason> {
+        self.last_stop.as_ref()
+    }
+}
+
+impl Chip8Emulator {
+    // Checked before every instruction while running. Stopping switches to step mode.
+    pub fn check_breakpoints(&mut self) -> Option<StopReason> {
+        let resuming = std::mem::take(&mut self.debugger.resuming);
+        if self.debugger.waiting {
+            return None;
+        }
+
+        let mut reason = None;
+        for index in 0..self.debugger.conditions.len() {
+            let (condition, was_true) = self.debugger.conditions[index];
+            let is_true = self.evaluate(condition);
+            if is_true && !was_true && !resuming {
+                reason.get_or_insert(StopReason::Condition(condition));
+            }
+            self.debugger.conditions[index].1 = is_true;
+        }
+
+        let (pc, sp) = (self.context.pc, self.context.sp);
+        if reason.is_none() && !resuming {
+            reason = match self.debugger.run_until {
+                Some(RunUntil::Step) => Some(StopReason::Step),
+                Some(RunUntil::Return {
+                    pc: target,
+                    sp: depth,
+                }) if pc == target && sp == depth => Some(StopReason::Step),
+                Some(RunUntil::StepOut { sp: depth }) if sp < depth => Some(StopReason::Step),
+                _ => None,
+            };
+        }
+        if reason.is_none() && !resuming && self.debugger.breakpoints.contains(&pc) {
+            reason = Some(StopReason::Breakpoint(pc));
+        }
+        if reason.is_none()
+            && !resuming
+            && let Some((access, range)) = self.memory_access()
+        {
+            reason = self
+                .debugger
+                .watchpoints
+                .iter()
+                .filter(|watchpoint| watchpoint.access.catches(access))
+                .find_map(|watchpoint| {
+                    let start = range.start.max(watchpoint.range.start);
+                    (start < range.end.min(watchpoint.range.end)).then_some(start)
+                })
+                .map(|address| StopReason::Watchpoint { address, access });
+        }
+
+        if reason.is_some() {
+            self.stop();
+            self.debugger.last_stop = reason.clone();
+        }
+        reason
+    }
+
+    pub fn add_condition(&mut self, condition: Condition) {
+        let is_true = self.evaluate(condition);
+        self.debugger.conditions.push((condition, is_true));
+    }
+
+    pub fn continue_running(&mut self) {
+        self.resume(None);
+    }
+
+    pub fn step_into(&mut self) {
+        self.resume(Some(RunUntil::Step));
+    }
+
+    // Runs a whole subroutine call as one step
+    pub fn step_over(&mut self) {
+        let until = match decode_at(&self.context.memory, self.context.pc) {
+            Some(instruction @ Instruction::Call(_)) => RunUntil::Return {
+                pc: self.context.pc + instruction.size(),
+                sp: self.context.sp,
+            },
+            _ => RunUntil::Step,
+        };
+        self.resume(Some(until));
+    }
+
+    // Runs until the current subroutine returns, false outside of a subroutine
+    pub fn step_out(&mut self) -> bool {
+        if self.context.sp == 0 {
+            return false;
+        }
+        self.resume(Some(RunUntil::StepOut {
+            sp: self.context.sp,
+        }));
+        true
+    }
+
+    pub fn pause(&mut self) {
+        if let EmulatorMode::Run = self.mode {
+            self.stop();
+        }
+    }
+
+    // The instruction at PC, as a disassembly line
+    pub fn location(&self) -> String {
+        self.disassembly(self.context.pc, 1)
+    }
+
+    // Runs a debugger command and returns its output. Resuming commands print nothing,
+    // the caller reports where execution stops next.
+    pub fn debug_command(&mut self, line: &str) -> String {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match self.run_command(&words) {
+            Ok(output) => output,
+            Err(error) => error,
+        }
+    }
+
+    fn run_command(&mut self, words: &[&str]) -> Result<String, String> {
+        let Some((&command, args)) = words.split_first() else {
+            return Ok(String::new());
+        };
+
+        if matches!(
+            command,
+            "step" | "s" | "next" | "n" | "finish" | "continue" | "c"
+        ) && let EmulatorMode::Halted = self.mode
+        {
+            return Err("The emulator is halted".to_string());
+        }
+
+        match (command, args) {
+            ("break" | "b", [address]) => {
+                let address = parse_number(address)?;
+                self.debugger.breakpoints.insert(address);
+                Ok(format!("Breakpoint at {:#05x}", address))
+            }
+            ("watch" | "w", [address, rest @ ..]) if rest.len() <= 2 => {
+                let address = parse_number(address)?;
+                let len = match rest.first() {
+                    Some(len) => parse_number(len)?,
+                    None => 1,
+                };
+                let access = match rest.get(1) {
+                    Some(&"r") => Access::Read,
+                    Some(&"w") | None => Access::Write,
+                    Some(&"rw") => Access::ReadWrite,
+                    Some(other) => return Err(format!("Unknown access '{}'", other)),
+                };
+                let end = address
+                    .checked_add(len.max(1))
+                    .ok_or_else(|| format!("Watchpoint length {} is too long", len))?;
+                let watchpoint = Watchpoint {
+                    range: address..end,
+                    access,
+                };
+                let output = format!("Watchpoint {}", watchpoint);
+                self.debugger.watchpoints.push(watchpoint);
+                Ok(output)
+            }
+            ("cond", [register, comparison, value]) => {
+                let condition = Condition {
+                    register: parse_register(register)?,
+                    comparison: parse_comparison(comparison)?,
+                    value: u16::try_from(parse_number(value)?)
+                        .map_err(|_| format!("Value '{}' is over 0xffff", value))?,
+                };
+                self.add_condition(condition);
+                Ok(format!("Condition {}", condition))
+            }
+            ("delete" | "d", []) => {
+                self.debugger.breakpoints.clear();
+                self.debugger.watchpoints.clear();
+                self.debugger.conditions.clear();
+                Ok("Deleted all breakpoints, watchpoints and conditions".to_string())
+            }
+            ("delete" | "d", [address]) => {
+                let address = parse_number(address)?;
+                if self.debugger.breakpoints.remove(&address) {
+                    Ok(format!("Deleted breakpoint at {:#05x}", address))
+                } else {
+                    Err(format!("No breakpoint at {:#05x}", address))
+                }
+            }
+            ("info", []) => Ok(self.info()),
+            ("regs" | "r", []) => Ok(self.registers()),
+            ("mem" | "m", [address, rest @ ..]) if rest.len() <= 1 => {
+                let address = parse_number(address)?;
+                let len = match rest.first() {
+                    Some(len) => parse_number(len)?,
+                    None => 16,
+                };
+                Ok(self.memory_dump(address, len))
+            }
+            ("disasm", rest) if rest.len() <= 2 => {
+                let address = match rest.first() {
+                    Some(address) => parse_number(address)?,
+                    None => self.context.pc,
+                };
+                let count = match rest.get(1) {
+                    Some(count) => parse_number(count)?,
+                    None => 8,
+                };
+                Ok(self.disassembly(address, count))
+            }
+            ("step" | "s", []) => {
+                self.step_into();
+                Ok(String::new())
+            }
+            ("next" | "n", []) => {
+                self.step_over();
+                Ok(String::new())
+            }
+            ("finish", []) => {
+                if self.step_out() {
+                    Ok(String::new())
+                } else {
+                    Err("Not in a subroutine".to_string())
+                }
+            }
+            ("continue" | "c", []) => {
+                self.continue_running();
+                Ok(String::new())
+            }
+            ("pause", []) => {
+                self.pause();
+                Ok(format!("Paused\n{}", self.location()))
+            }
+            ("help" | "h", []) => Ok(HELP.to_string()),
+            _ => Err(format!("Unknown command '{}', try help", words.join(" "))),
+        }
+    }
+
+    fn resume(&mut self, until: Option<RunUntil>) {
+        self.debugger.run_until = until;
+        self.debugger.last_stop = None;
+        self.debugger.resuming = true;
+        self.mode = EmulatorMode::Run;
+    }
+
+    fn stop(&mut self) {
+        self.debugger.run_until = None;
+        self.mode = EmulatorMode::Step;
+    }
+
+    fn evaluate(&self, condition: Condition) -> bool {
+        let value = match condition.register {
+            Register::V(x) => self.context.v[x as usize] as u16,
+            Register::I => self.context.i,
+            Register::Sp => self.context.sp as u16,
+            Register::Delay => self.context.delay as u16,
+            Register::Sound => self.context.sound as u16,
+        };
+        match condition.comparison {
+            Comparison::Eq => value == condition.value,
+            Comparison::Ne => value != condition.value,
+            Comparison::Lt => value < condition.value,
+            Comparison::Le => value <= condition.value,
+            Comparison::Gt => value > condition.value,
+            Comparison::Ge => value >= condition.value,
+        }
+    }
+
+    // The memory the instruction at PC is about to read or write, fonts aside
+    fn memory_access(&self) -> Option<(Access, Range<usize>)> {
+        let i = self.context.i as usize;
+        let (access, len) = match decode_at(&self.context.memory, self.context.pc)? {
+            Instruction::Draw(_, _, n) => {
+                let rows = if n == 0 { 32 } else { n as usize };
+                let planes = self.context.frame_buffer.planes().count_ones() as usize;
+                (Access::Read, rows * planes)
+            }
+            Instruction::Load(x) => (Access::Read, x as usize + 1),
+            Instruction::LoadRange(x, y) => (Access::Read, x.abs_diff(y) as usize + 1),
+            Instruction::Audio => (Access::Read, 16),
+            Instruction::Store(x) => (Access::Write, x as usize + 1),
+            Instruction::SaveRange(x, y) => (Access::Write, x.abs_diff(y) as usize + 1),
+            Instruction::Bcd(_) => (Access::Write, 3),
+            _ => return None,
+        };
+        Some((access, i..i + len))
+    }
+
+    fn info(&self) -> String {
+        let debugger = &self.debugger;
+        let mut lines: Vec<String> = Vec::new();
+        for address in &debugger.breakpoints {
+            lines.push(format!("Breakpoint at {:#05x}", address));
+        }
+        for watchpoint in &debugger.watchpoints {
+            lines.push(format!("Watchpoint {}", watchpoint));
+        }
+        for (condition, _) in &debugger.conditions {
+            lines.push(format!("Condition {}", condition));
+        }
+        if lines.is_empty() {
+            lines.push("No breakpoints, watchpoints or conditions".to_string());
+        }
+        lines.join("\n")
+    }
+
+    // Registers and the call stack, as shown by the regs command
+    pub fn registers(&self) -> String {
+        let context = &self.context;
+        let bytes = |range: Range<usize>| -> String {
+            context.v[range]
+                .iter()
+                .map(|v| format!("{:02X}", v))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let stack: Vec<String> = context.stack[..context.sp]
+            .iter()
+            .map(|address| format!("{:04X}", address))
+            .collect();
+
+        format!(
+            "V0-V7: {}\nV8-VF: {}\nPC: {:04X}  I: {:04X}  SP: {}  DT: {:02X}  ST: {:02X}\nStack: {}",
+            bytes(0..8),
+            bytes(8..16),
+            context.pc,
+            context.i,
+            context.sp,
+            context.delay,
+            context.sound,
+            stack.join(" ")
+        )
+    }
+
+    fn memory_dump(&self, address: usize, len: usize) -> String {
+        let memory = &self.context.memory;
+        let end = address.saturating_add(len).min(memory.len());
+        if address >= end {
+            return format!("Address {:#05x} is outside of memory", address);
+        }
+
+        (address..end)
+            .step_by(16)
+            .map(|row| {
+                let bytes: Vec<String> = memory[row..(row + 16).min(end)]
+                    .iter()
+                    .map(|byte| format!("{:02X}", byte))
+                    .collect();
+                format!("{:04X}: {}", row, bytes.join(" "))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // Instructions are at most four bytes long
+    fn disassembly(&self, address: usize, count: usize) -> String {
+        let memory = &self.context.memory;
+        let start = address.min(memory.len());
+        let end = start
+            .saturating_add(count.saturating_mul(4))
+            .min(memory.len());
+
+        disassemble(&memory[start..end], start)
+            .iter()
+            .take(count)
+            .map(|line| {
+                // Point at the next instruction to run
+                let text = line.to_string();
+                if line.address == self.context.pc {
+                    format!("=> {}", &text[3..])
+                } else {
+                    text
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+// Hexadecimal with 0x, decimal otherwise
+fn parse_number(text: &str) -> Result<usize, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("Invalid number '{}'", text))
+}
+
+fn parse_register(text: &str) -> Result<Register, String> {
+    let lower = text.to_ascii_lowercase();
+    match lower.as_str() {
+        "i" => Ok(Register::I),
+        "sp" => Ok(Register::Sp),
+        "dt" => Ok(Register::Delay),
+        "st" => Ok(Register::Sound),
+        _ => lower
+            .strip_prefix('v')
+            .filter(|x| x.len() == 1)
+            .and_then(|x| u8::from_str_radix(x, 16).ok())
+            .map(Register::V)
+            .ok_or_else(|| format!("Unknown register '{}'", text)),
+    }
+}
+
+fn parse_comparison(text: &str) -> Result<Comparison, String> {
+    match text {
+        "==" => Ok(Comparison::Eq),
+        "!=" => Ok(Comparison::Ne),
+        "<" => Ok(Comparison::Lt),
+        "<=" => Ok(Comparison::Le),
+        ">" => Ok(Comparison::Gt),
+        ">=" => Ok(Comparison::Ge),
+        _ => Err(format!("Unknown comparison '{}'", text)),
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+            Access::ReadWrite => write!(f, "read/write"),
+        }
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:#05x}..{:#05x} ({})",
+            self.range.start, self.range.end, self.access
+        )
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::V(x) => write!(f, "V{:X}", x),
+            Register::I => write!(f, "I"),
+            Register::Sp => write!(f, "SP"),
+            Register::Delay => write!(f, "DT"),
+            Register::Sound => write!(f, "ST"),
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {:#x}", self.register, self.comparison, self.value)
+    }
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Breakpoint(address) => write!(f, "Breakpoint at {:#05x}", address),
+            StopReason::Watchpoint { address, access } => {
+                write!(f, "Watchpoint: {} of {:#05x}", access, address)
+            }
+            StopReason::Condition(condition) => write!(f, "Condition {}", condition),
+            StopReason::Step => write!(f, "Stepped"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{assembler::assemble, emulator::FrameOutcome};
+
+    fn emulator(source: &str) -> Chip8Emulator {
+        let mut chip8 = Chip8Emulator::new(EmulatorMode::Run);
+        chip8.load_rom(&assemble(source).unwrap()).unwrap();
+        chip8
+    }
+
+    fn stopped(chip8: &mut Chip8Emulator) -> StopReason {
+        match chip8.tick_frame().unwrap() {
+            FrameOutcome::Stopped(reason) => reason,
+            outcome => panic!("expected a stop, got {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn arguments_are_parsed() {
+        assert_eq!(parse_number("0x2A"), Ok(42));
+        assert_eq!(parse_number("0X2a"), Ok(42));
+        assert_eq!(parse_number("42"), Ok(42));
+        assert!(parse_number("2A").is_err());
+        assert!(parse_number("-1").is_err());
+        assert!(parse_number("0x").is_err());
+
+        assert_eq!(parse_register("vA"), Ok(Register::V(0xA)));
+        assert_eq!(parse_register("I"), Ok(Register::I));
+        assert_eq!(parse_register("dt"), Ok(Register::Delay));
+        assert!(parse_register("v10").is_err());
+        assert!(parse_register("vg").is_err());
+
+        assert_eq!(parse_comparison(">="), Ok(Comparison::Ge));
+        assert!(parse_comparison("=").is_err());
+    }
+
+    #[test]
+    fn commands_report_what_they_set() {
+        let mut chip8 = emulator("CLS");
+        assert_eq!(chip8.debug_command("b 0x204"), "Breakpoint at 0x204");
+        assert_eq!(
+            chip8.debug_command("watch 0x300 4 rw"),
+            "Watchpoint 0x300..0x304 (read/write)"
+        );
+        assert_eq!(chip8.debug_command("cond v3 == 16"), "Condition V3 == 0x10");
+        assert_eq!(
+            chip8.debug_command("info"),
+            "Breakpoint at 0x204\nWatchpoint 0x300..0x304 (read/write)\nCondition V3 == 0x10"
+        );
+        assert_eq!(
+            chip8.debug_command("delete 0x204"),
+            "Deleted breakpoint at 0x204"
+        );
+        assert_eq!(
+            chip8.debug_command("delete 0x204"),
+            "No breakpoint at 0x204"
+        );
+        assert_eq!(chip8.debug_command("watch 0x300 4 x"), "Unknown access 'x'");
+        assert_eq!(
+            chip8.debug_command("frob"),
+            "Unknown command 'frob', try help"
+        );
+        assert_eq!(chip8.debug_command("mem 0x200 2"), "0200: 00 E0");
+        assert_eq!(
+            chip8.debug_command("disasm 0x200 1"),
+            "=>  CLS                     ; 0200: 00E0"
+        );
+    }
+
+    #[test]
+    fn huge_arguments_are_rejected_or_clamped() {
+        let mut chip8 = emulator("CLS");
+        let max = usize::MAX.to_string();
+
+        assert_eq!(
+            chip8.debug_command(&format!("watch 0x100 {}", max)),
+            format!("Watchpoint length {} is too long", max)
+        );
+        assert_eq!(
+            chip8.debug_command(&format!("watch {} 2", max)),
+            "Watchpoint length 2 is too long"
+        );
+        assert!(
+            chip8
+                .debug_command(&format!("watch {}", max))
+                .starts_with("Watchpoint length")
+        );
+        assert!(chip8.debugger.watchpoints.is_empty());
+
+        assert_eq!(
+            chip8.debug_command("cond i == 0x10000"),
+            "Value '0x10000' is over 0xffff"
+        );
+        assert_eq!(
+            chip8.debug_command(&format!("mem {}", max)),
+            format!("Address {:#05x} is outside of memory", usize::MAX)
+        );
+        assert_eq!(
+            chip8.debug_command(&format!("mem 0xFFE {}", max)),
+            "0FFE: 00 00"
+        );
+        assert_eq!(chip8.debug_command(&format!("disasm {} {}", max, max)), "");
+        assert!(
+            chip8
+                .debug_command(&format!("disasm 0x200 {}", max))
+                .starts_with("=>  CLS")
+        );
+    }
+
+    #[test]
+    fn breakpoint_stops_before_the_instruction() {
+        let mut chip8 = emulator("LD V0, 1\nLD V1, 2\nend:\nJP end");
+        chip8.debug_command("break 0x202");
+
+        assert_eq!(stopped(&mut chip8), StopReason::Breakpoint(0x202));
+        assert_eq!((chip8.context.pc, chip8.context.v[1]), (0x202, 0));
+        assert!(matches!(chip8.mode, EmulatorMode::Step));
+
+        // Continuing runs the instruction stopped at rather than stopping again
+        chip8.debug_command("continue");
+        assert_eq!(chip8.tick_frame(), Ok(FrameOutcome::Complete));
+        assert_eq!(chip8.context.v[1], 2);
+    }
+
+    #[test]
+    fn watchpoints_stop_on_matching_access() {
+        let source = "LD I, 0x300\nLD V0, [I]\nLD [I], V1\nend:\nJP end";
+
+        let mut chip8 = emulator(source);
+        chip8.debug_command("watch 0x301");
+        assert_eq!(
+            stopped(&mut chip8),
+            StopReason::Watchpoint {
+                address: 0x301,
+                access: Access::Write
+            }
+        );
+        assert_eq!(chip8.context.pc, 0x204);
+
+        let mut chip8 = emulator(source);
+        chip8.debug_command("watch 0x2F0 0x11 r");
+        assert_eq!(
+            stopped(&mut chip8),
+            StopReason::Watchpoint {
+                address: 0x300,
+                access: Access::Read
+            }
+        );
+        assert_eq!(chip8.context.pc, 0x202);
+
+        // Outside the range the instructions touch
+        let mut chip8 = emulator(source);
+        chip8.debug_command("watch 0x302 2 rw");
+        assert_eq!(chip8.tick_frame(), Ok(FrameOutcome::Complete));
+    }
+
+    #[test]
+    fn conditions_stop_when_they_become_true() {
+        let mut chip8 = emulator("loop:\nADD V2, 1\nJP loop");
+        chip8.debug_command("cond v2 >= 3");
+
+        assert_eq!(
+            stopped(&mut chip8),
+            StopReason::Condition(Condition {
+                register: Register::V(2),
+                comparison: Comparison::Ge,
+                value: 3,
+            })
+        );
+        assert_eq!(chip8.context.v[2], 3);
+
+        // Still true, so running on does not stop again until it turns false and back
+        chip8.debug_command("continue");
+        assert_eq!(chip8.tick_frame(), Ok(FrameOutcome::Complete));
+    }
+
+    #[test]
+    fn next_steps_over_calls_and_finish_returns() {
+        let mut chip8 = emulator("CALL sub\nend:\nJP end\nsub:\nLD V0, 1\nLD V1, 2\nRET");
+        chip8.debug_command("next");
+        assert_eq!(stopped(&mut chip8), StopReason::Step);
+        assert_eq!((chip8.context.pc, chip8.context.v[1]), (0x202, 2));
+
+        let mut chip8 = emulator("CALL sub\nend:\nJP end\nsub:\nLD V0, 1\nLD V1, 2\nRET");
+        chip8.debug_command("step");
+        assert_eq!(stopped(&mut chip8), StopReason::Step);
+        assert_eq!(chip8.context.pc, 0x204);
+        chip8.debug_command("finish");
+        assert_eq!(stopped(&mut chip8), StopReason::Step);
+        assert_eq!(chip8.context.pc, 0x202);
+        assert_eq!(chip8.debug_command("finish"), "Not in a subroutine");
+    }
+}
@@ -0,0 +1,348 @@
+use std::fmt;
+
+use super::quirks::Platform;
+
+// A decoded instruction. X, Y are register indices, NN bytes and NNN addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    // 0NNN, machine code routine, ignored
+    Sys(u16),
+    // 00E0
+    Cls,
+    // 00EE
+    Ret,
+    // 00CN
+    ScrollDown(u8),
+    // 00DN
+    ScrollUp(u8),
+    // 00FB
+    ScrollRight,
+    // 00FC
+    ScrollLeft,
+    // 00FD
+    Exit,
+    // 00FE
+    Lores,
+    // 00FF
+    Hires,
+    // 1NNN
+    Jump(u16),
+    // 2NNN
+    Call(u16),
+    // 3XNN
+    SkipEqImm(u8, u8),
+    // 4XNN
+    SkipNeImm(u8, u8),
+    // 5XY0
+    SkipEq(u8, u8),
+    // 5XY2
+    SaveRange(u8, u8),
+    // 5XY3
+    LoadRange(u8, u8),
+    // 6XNN
+    LoadImm(u8, u8),
+    // 7XNN
+    AddImm(u8, u8),
+    // 8XY0
+    Move(u8, u8),
+    // 8XY1
+    Or(u8, u8),
+    // 8XY2
+    And(u8, u8),
+    // 8XY3
+    Xor(u8, u8),
+    // 8XY4
+    Add(u8, u8),
+    // 8XY5
+    Sub(u8, u8),
+    // 8XY6
+    ShiftRight(u8, u8),
+    // 8XY7
+    SubReverse(u8, u8),
+    // 8XYE
+    ShiftLeft(u8, u8),
+    // 9XY0
+    SkipNe(u8, u8),
+    // ANNN
+    LoadI(u16),
+    // BNNN
+    JumpOffset(u16),
+    // CXNN
+    Random(u8, u8),
+    // DXYN
+    Draw(u8, u8, u8),
+    // EX9E
+    SkipKey(u8),
+    // EXA1
+    SkipNotKey(u8),
+    // F000 NNNN, the address is the word following the opcode
+    LoadLongI(u16),
+    // FN01
+    Plane(u8),
+    // F002
+    Audio,
+    // FX07
+    LoadDelay(u8),
+    // FX0A
+    WaitKey(u8),
+    // FX15
+    SetDelay(u8),
+    // FX18
+    SetSound(u8),
+    // FX1E
+    AddI(u8),
+    // FX29
+    Font(u8),
+    // FX30
+    BigFont(u8),
+    // FX33
+    Bcd(u8),
+    // FX3A
+    Pitch(u8),
+    // FX55
+    Store(u8),
+    // FX65
+    Load(u8),
+    // FX75
+    SaveFlags(u8),
+    // FX85
+    LoadFlags(u8),
+    Unknown(u16),
+}
+
+impl Instruction {
+    // Size in bytes, F000 NNNN is the only four byte instruction
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::LoadLongI(_) => 4,
+            _ => 2,
+        }
+    }
+
+    // The first platform with the instruction, SUPER-CHIP or XO-CHIP for their extensions
+    pub fn platform(&self) -> Platform {
+        match self {
+            Instruction::ScrollDown(_)
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::Exit
+            | Instruction::Lores
+            | Instruction::Hires
+            | Instruction::Draw(_, _, 0)
+            | Instruction::BigFont(_)
+            | Instruction::SaveFlags(_)
+            | Instruction::LoadFlags(_) => Platform::SuperChip,
+            Instruction::ScrollUp(_)
+            | Instruction::SaveRange(..)
+            | Instruction::LoadRange(..)
+            | Instruction::LoadLongI(_)
+            | Instruction::Plane(_)
+            | Instruction::Audio
+            | Instruction::Pitch(_) => Platform::XoChip,
+            _ => Platform::Chip8,
+        }
+    }
+
+    // Inverse of decode_at, big-endian bytes of the instruction
+    pub fn encode(&self) -> Vec<u8> {
+        let xy = |prefix: u16, x: u8, y: u8, n: u16| -> u16 {
+            prefix << 12 | (x as u16) << 8 | (y as u16) << 4 | n
+        };
+        let xnn =
+            |prefix: u16, x: u8, nn: u8| -> u16 { prefix << 12 | (x as u16) << 8 | nn as u16 };
+        let fx = |x: u8, low: u16| -> u16 { 0xF000 | (x as u16) << 8 | low };
+
+        let opcode = match *self {
+            Instruction::Sys(nnn) => nnn & 0xFFF,
+            Instruction::Cls => 0x00E0,
+            Instruction::Ret => 0x00EE,
+            Instruction::ScrollDown(n) => 0x00C0 | n as u16,
+            Instruction::ScrollUp(n) => 0x00D0 | n as u16,
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::Lores => 0x00FE,
+            Instruction::Hires => 0x00FF,
+            Instruction::Jump(nnn) => 0x1000 | nnn & 0xFFF,
+            Instruction::Call(nnn) => 0x2000 | nnn & 0xFFF,
+            Instruction::SkipEqImm(x, nn) => xnn(3, x, nn),
+            Instruction::SkipNeImm(x, nn) => xnn(4, x, nn),
+            Instruction::SkipEq(x, y) => xy(5, x, y, 0),
+            Instruction::SaveRange(x, y) => xy(5, x, y, 2),
+            Instruction::LoadRange(x, y) => xy(5, x, y, 3),
+            Instruction::LoadImm(x, nn) => xnn(6, x, nn),
+            Instruction::AddImm(x, nn) => xnn(7, x, nn),
+            Instruction::Move(x, y) => xy(8, x, y, 0),
+            Instruction::Or(x, y) => xy(8, x, y, 1),
+            Instruction::And(x, y) => xy(8, x, y, 2),
+            Instruction::Xor(x, y) => xy(8, x, y, 3),
+            Instruction::Add(x, y) => xy(8, x, y, 4),
+            Instruction::Sub(x, y) => xy(8, x, y, 5),
+            Instruction::ShiftRight(x, y) => xy(8, x, y, 6),
+            Instruction::SubReverse(x, y) => xy(8, x, y, 7),
+            Instruction::ShiftLeft(x, y) => xy(8, x, y, 0xE),
+            Instruction::SkipNe(x, y) => xy(9, x, y, 0),
+            Instruction::LoadI(nnn) => 0xA000 | nnn & 0xFFF,
+            Instruction::JumpOffset(nnn) => 0xB000 | nnn & 0xFFF,
+            Instruction::Random(x, nn) => xnn(0xC, x, nn),
+            Instruction::Draw(x, y, n) => xy(0xD, x, y, n as u16),
+            Instruction::SkipKey(x) => xnn(0xE, x, 0x9E),
+            Instruction::SkipNotKey(x) => xnn(0xE, x, 0xA1),
+            Instruction::LoadLongI(nnnn) => return vec![0xF0, 0x00, (nnnn >> 8) as u8, nnnn as u8],
+            Instruction::Plane(n) => fx(n, 0x01),
+            Instruction::Audio => 0xF002,
+            Instruction::LoadDelay(x) => fx(x, 0x07),
+            Instruction::WaitKey(x) => fx(x, 0x0A),
+            Instruction::SetDelay(x) => fx(x, 0x15),
+            Instruction::SetSound(x) => fx(x, 0x18),
+            Instruction::AddI(x) => fx(x, 0x1E),
+            Instruction::Font(x) => fx(x, 0x29),
+            Instruction::BigFont(x) => fx(x, 0x30),
+            Instruction::Bcd(x) => fx(x, 0x33),
+            Instruction::Pitch(x) => fx(x, 0x3A),
+            Instruction::Store(x) => fx(x, 0x55),
+            Instruction::Load(x) => fx(x, 0x65),
+            Instruction::SaveFlags(x) => fx(x, 0x75),
+            Instruction::LoadFlags(x) => fx(x, 0x85),
+            Instruction::Unknown(opcode) => opcode,
+        };
+
+        opcode.to_be_bytes().to_vec()
+    }
+}
+
+// Decodes a single opcode. F000 decodes to LoadLongI(0) since its address is in the next word,
+// use decode_at to read it as well.
+pub fn decode(opcode: u16) -> Instruction {
+    let x = ((opcode >> 8) & 0xF) as u8;
+    let y = ((opcode >> 4) & 0xF) as u8;
+    let n = (opcode & 0xF) as u8;
+    let nn = (opcode & 0xFF) as u8;
+    let nnn = opcode & 0xFFF;
+
+    match ((opcode >> 12) & 0xF, x, y, n) {
+        (0, 0, 0xE, 0) => Instruction::Cls,
+        (0, 0, 0xE, 0xE) => Instruction::Ret,
+        (0, 0, 0xC, _) => Instruction::ScrollDown(n),
+        (0, 0, 0xD, _) => Instruction::ScrollUp(n),
+        (0, 0, 0xF, 0xB) => Instruction::ScrollRight,
+        (0, 0, 0xF, 0xC) => Instruction::ScrollLeft,
+        (0, 0, 0xF, 0xD) => Instruction::Exit,
+        (0, 0, 0xF, 0xE) => Instruction::Lores,
+        (0, 0, 0xF, 0xF) => Instruction::Hires,
+        (0, _, _, _) => Instruction::Sys(nnn),
+        (1, _, _, _) => Instruction::Jump(nnn),
+        (2, _, _, _) => Instruction::Call(nnn),
+        (3, _, _, _) => Instruction::SkipEqImm(x, nn),
+        (4, _, _, _) => Instruction::SkipNeImm(x, nn),
+        (5, _, _, 0) => Instruction::SkipEq(x, y),
+        (5, _, _, 2) => Instruction::SaveRange(x, y),
+        (5, _, _, 3) => Instruction::LoadRange(x, y),
+        (6, _, _, _) => Instruction::LoadImm(x, nn),
+        (7, _, _, _) => Instruction::AddImm(x, nn),
+        (8, _, _, 0) => Instruction::Move(x, y),
+        (8, _, _, 1) => Instruction::Or(x, y),
+        (8, _, _, 2) => Instruction::And(x, y),
+        (8, _, _, 3) => Instruction::Xor(x, y),
+        (8, _, _, 4) => Instruction::Add(x, y),
+        (8, _, _, 5) => Instruction::Sub(x, y),
+        (8, _, _, 6) => Instruction::ShiftRight(x, y),
+        (8, _, _, 7) => Instruction::SubReverse(x, y),
+        (8, _, _, 0xE) => Instruction::ShiftLeft(x, y),
+        (9, _, _, 0) => Instruction::SkipNe(x, y),
+        (0xA, _, _, _) => Instruction::LoadI(nnn),
+        (0xB, _, _, _) => Instruction::JumpOffset(nnn),
+        (0xC, _, _, _) => Instruction::Random(x, nn),
+        (0xD, _, _, _) => Instruction::Draw(x, y, n),
+        (0xE, _, 9, 0xE) => Instruction::SkipKey(x),
+        (0xE, _, 0xA, 1) => Instruction::SkipNotKey(x),
+        (0xF, 0, 0, 0) => Instruction::LoadLongI(0),
+        (0xF, _, 0, 1) => Instruction::Plane(x),
+        (0xF, 0, 0, 2) => Instruction::Audio,
+        (0xF, _, 0, 7) => Instruction::LoadDelay(x),
+        (0xF, _, 0, 0xA) => Instruction::WaitKey(x),
+        (0xF, _, 1, 5) => Instruction::SetDelay(x),
+        (0xF, _, 1, 8) => Instruction::SetSound(x),
+        (0xF, _, 1, 0xE) => Instruction::AddI(x),
+        (0xF, _, 2, 9) => Instruction::Font(x),
+        (0xF, _, 3, 0) => Instruction::BigFont(x),
+        (0xF, _, 3, 3) => Instruction::Bcd(x),
+        (0xF, _, 3, 0xA) => Instruction::Pitch(x),
+        (0xF, _, 5, 5) => Instruction::Store(x),
+        (0xF, _, 6, 5) => Instruction::Load(x),
+        (0xF, _, 7, 5) => Instruction::SaveFlags(x),
+        (0xF, _, 8, 5) => Instruction::LoadFlags(x),
+        _ => Instruction::Unknown(opcode),
+    }
+}
+
+// Decodes the instruction at address, None when it does not fit in memory
+pub fn decode_at(memory: &[u8], address: usize) -> Option<Instruction> {
+    let word = |at: usize| -> Option<u16> {
+        Some(((*memory.get(at)? as u16) << 8) | *memory.get(at + 1)? as u16)
+    };
+
+    match decode(word(address)?) {
+        Instruction::LoadLongI(_) => Some(Instruction::LoadLongI(word(address + 2)?)),
+        instruction => Some(instruction),
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Instruction::Sys(nnn) => write!(f, "SYS 0x{:03X}", nnn),
+            Instruction::Cls => write!(f, "CLS"),
+            Instruction::Ret => write!(f, "RET"),
+            Instruction::ScrollDown(n) => write!(f, "SCD {}", n),
+            Instruction::ScrollUp(n) => write!(f, "SCU {}", n),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::Lores => write!(f, "LOW"),
+            Instruction::Hires => write!(f, "HIGH"),
+            Instruction::Jump(nnn) => write!(f, "JP 0x{:03X}", nnn),
+            Instruction::Call(nnn) => write!(f, "CALL 0x{:03X}", nnn),
+            Instruction::SkipEqImm(x, nn) => write!(f, "SE V{:X}, 0x{:02X}", x, nn),
+            Instruction::SkipNeImm(x, nn) => write!(f, "SNE V{:X}, 0x{:02X}", x, nn),
+            Instruction::SkipEq(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::SaveRange(x, y) => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            Instruction::LoadRange(x, y) => write!(f, "LOAD V{:X}, V{:X}", x, y),
+            Instruction::LoadImm(x, nn) => write!(f, "LD V{:X}, 0x{:02X}", x, nn),
+            Instruction::AddImm(x, nn) => write!(f, "ADD V{:X}, 0x{:02X}", x, nn),
+            Instruction::Move(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::Add(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubReverse(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipNe(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LoadI(nnn) => write!(f, "LD I, 0x{:03X}", nnn),
+            Instruction::JumpOffset(nnn) => write!(f, "JP V0, 0x{:03X}", nnn),
+            Instruction::Random(x, nn) => write!(f, "RND V{:X}, 0x{:02X}", x, nn),
+            Instruction::Draw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipKey(x) => write!(f, "SKP V{:X}", x),
+            Instruction::SkipNotKey(x) => write!(f, "SKNP V{:X}", x),
+            Instruction::LoadLongI(nnnn) => write!(f, "LD I, LONG 0x{:04X}", nnnn),
+            Instruction::Plane(n) => write!(f, "PLANE {}", n),
+            Instruction::Audio => write!(f, "AUDIO"),
+            Instruction::LoadDelay(x) => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitKey(x) => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelay(x) => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSound(x) => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddI(x) => write!(f, "ADD I, V{:X}", x),
+            Instruction::Font(x) => write!(f, "LD F, V{:X}", x),
+            Instruction::BigFont(x) => write!(f, "LD HF, V{:X}", x),
+            Instruction::Bcd(x) => write!(f, "LD B, V{:X}", x),
+            Instruction::Pitch(x) => write!(f, "PITCH V{:X}", x),
+            Instruction::Store(x) => write!(f, "LD [I], V{:X}", x),
+            Instruction::Load(x) => write!(f, "LD V{:X}, [I]", x),
+            Instruction::SaveFlags(x) => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags(x) => write!(f, "LD V{:X}, R", x),
+            Instruction::Unknown(opcode) => write!(f, "DW 0x{:04X}", opcode),
+        }
+    }
+}
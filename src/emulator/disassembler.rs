@@ -0,0 +1,96 @@
+use std::{collections::BTreeMap, fmt};
+
+use super::decoder::{Instruction, decode_at};
+
+// One line of a disassembly listing. A trailing odd byte has no instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub address: usize,
+    pub bytes: Vec<u8>,
+    pub instruction: Option<Instruction>,
+}
+
+// Linear sweep over the ROM, decoding every word as an instruction. Data shows up as
+// DW lines for opcodes that do not decode.
+pub fn disassemble(rom: &[u8], origin: usize) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    while offset < rom.len() {
+        let (instruction, size) = match decode_at(rom, offset) {
+            Some(instruction) => (Some(instruction), instruction.size()),
+            // F000 without its address word, or a single trailing byte
+            None if rom.len() - offset >= 2 => (Some(Instruction::Unknown(word(rom, offset))), 2),
+            None => (None, 1),
+        };
+
+        lines.push(Line {
+            address: origin + offset,
+            bytes: rom[offset..offset + size].to_vec(),
+            instruction,
+        });
+        offset += size;
+    }
+
+    lines
+}
+
+// Follows the program from its first instruction through jumps, calls and both sides of
+// skips, so sprite data is not mistaken for code like in a linear sweep. Returns the
+// instructions reached, by address. Computed jumps (BNNN) and code written at run time are
+// not followed.
+pub fn trace(rom: &[u8], origin: usize) -> BTreeMap<usize, Instruction> {
+    let mut reached = BTreeMap::new();
+    let mut pending = vec![origin];
+
+    while let Some(address) = pending.pop() {
+        if address < origin || reached.contains_key(&address) {
+            continue;
+        }
+        let Some(instruction) = decode_at(rom, address - origin) else {
+            continue;
+        };
+        reached.insert(address, instruction);
+
+        let next = address + instruction.size();
+        match instruction {
+            Instruction::Jump(nnn) => pending.push(nnn as usize),
+            Instruction::Call(nnn) => pending.extend([nnn as usize, next]),
+            Instruction::SkipEqImm(..)
+            | Instruction::SkipNeImm(..)
+            | Instruction::SkipEq(..)
+            | Instruction::SkipNe(..)
+            | Instruction::SkipKey(_)
+            | Instruction::SkipNotKey(_) => {
+                // Skipping over F000 NNNN skips all four bytes
+                let skipped = decode_at(rom, next - origin).map_or(2, |skipped| skipped.size());
+                pending.extend([next, next + skipped]);
+            }
+            Instruction::Ret | Instruction::Exit | Instruction::JumpOffset(_) => {}
+            // Most likely data the program never runs
+            Instruction::Unknown(_) => {
+                reached.remove(&address);
+            }
+            _ => pending.push(next),
+        }
+    }
+
+    reached
+}
+
+fn word(rom: &[u8], offset: usize) -> u16 {
+    ((rom[offset] as u16) << 8) | rom[offset + 1] as u16
+}
+
+// Mnemonic first so the listing can be fed back to the assembler, address and bytes as a comment
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = match self.instruction {
+            Some(instruction) => instruction.to_string(),
+            None => format!("DB 0x{:02X}", self.bytes[0]),
+        };
+        let bytes: String = self.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+
+        write!(f, "    {:<24}; {:04X}: {}", mnemonic, self.address, bytes)
+    }
+}
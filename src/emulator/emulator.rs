@@ -1,22 +1,80 @@
-use std::{f32::consts::PI, fs::File, io::Read};
+use std::{fs::File, io::Read};
 
-use sdl2::{audio::AudioQueue, keyboard::Keycode};
+use sha1::{Digest, Sha1};
 
-use super::{chip8_context::Chip8Context, font::FONTS};
+use super::{
+    chip8_context::{Chip8Context, DEFAULT_INSTRUCTIONS_PER_FRAME},
+    debugger::{Debugger, StopReason},
+    error::{Chip8Error, StepOutcome},
+    font::{BIG_FONTS, FONTS},
+    movie::{Movie, MovieEvent},
+    quirks::{Platform, Quirks},
+    random::{Generator, RandomSource},
+    sound::Sound,
+    timing::Timing,
+};
 
 pub const FONT_OFFSET: u8 = 0x050;
+pub const BIG_FONT_OFFSET: u8 = 0x0A0;
 pub const ROM_OFFSET: usize = 0x200;
 
 #[derive(Debug)]
 pub struct Chip8Emulator {
     pub context: Chip8Context,
     pub mode: EmulatorMode,
+    pub quirks: Quirks,
+
+    // SHA-1 of the loaded ROM, identifies the game in save states
+    pub rom_hash: [u8; 20],
+
+    pub debugger: Debugger,
+
+    pub timing: Timing,
+    pub(crate) machine_cycles: u64,
+
+    // Instructions each tick_frame runs with fixed timing
+    pub instructions_per_frame: u32,
+
+    // Source of the CXNN random numbers, seeded with 0 until seed_random is called
+    pub random: Box<dyn RandomSource>,
+
+    // Frames run since power on, and the instructions run so far in the current one
+    pub(crate) frame: u64,
+    pub(crate) frame_instructions: u32,
+
+    // Instructions run since power on
+    pub(crate) instructions: u64,
+
+    // Key presses are added to the movie while it is set
+    pub recording: Option<Movie>,
+}
+
+// How a frame run by tick_frame ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameOutcome {
+    // The frame ran to the end and the timers ticked
+    Complete,
+    // A breakpoint, watchpoint or condition stopped the program before the end of the frame
+    Stopped(StopReason),
+    // The SUPER-CHIP exit instruction
+    Exit,
 }
 
 #[derive(Debug)]
 pub enum EmulatorMode {
     Run,
     Step,
+    // Stopped after a fault, only quitting is possible
+    Halted,
+}
+
+// A ROM's SHA-1 in hex, how config files and databases name a ROM. Known before loading,
+// so settings like the platform can be applied first.
+pub fn rom_id(data: &[u8]) -> String {
+    Sha1::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
 }
 
 impl Chip8Emulator {
@@ -24,6 +82,17 @@ impl Chip8Emulator {
         let mut out = Chip8Emulator {
             context: Chip8Context::new(),
             mode,
+            quirks: Quirks::default(),
+            rom_hash: [0; 20],
+            debugger: Debugger::default(),
+            timing: Timing::default(),
+            machine_cycles: 0,
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            random: Generator::default().source(0),
+            frame: 0,
+            frame_instructions: 0,
+            instructions: 0,
+            recording: None,
         };
 
         out.load_font();
@@ -31,8 +100,38 @@ impl Chip8Emulator {
         out
     }
 
+    // Selects the quirks and memory size of the given platform, must be called before loading a ROM
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.quirks = Quirks::from(platform);
+        self.context.memory.resize(platform.memory_size(), 0);
+    }
+
+    pub fn seed_random(&mut self, generator: Generator, seed: u64) {
+        self.random = generator.source(seed);
+    }
+
     pub fn read_rom_into_memory(&mut self, mut rom: File) -> Result<usize, std::io::Error> {
-        rom.read(&mut self.context.memory[ROM_OFFSET..])
+        let mut data = Vec::new();
+        rom.read_to_end(&mut data)?;
+        self.load_rom(&data)
+    }
+
+    pub fn load_rom(&mut self, data: &[u8]) -> Result<usize, std::io::Error> {
+        let available = self.context.memory.len() - ROM_OFFSET;
+        if data.len() > available {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "ROM is {} bytes, only {} fit in memory",
+                    data.len(),
+                    available
+                ),
+            ));
+        }
+
+        self.context.memory[ROM_OFFSET..ROM_OFFSET + data.len()].copy_from_slice(data);
+        self.rom_hash = Sha1::digest(data).into();
+        Ok(data.len())
     }
 
     pub fn load_font(&mut self) {
@@ -45,61 +144,171 @@ impl Chip8Emulator {
                 index += 1;
             }
         }
+
+        let index = BIG_FONT_OFFSET as usize;
+        let flat_big_fonts = BIG_FONTS.as_flattened();
+        self.context.memory[index..index + flat_big_fonts.len()].copy_from_slice(flat_big_fonts);
     }
 
-    pub fn set_keydown(&mut self, keycode: Keycode) {
-        let char = Chip8Emulator::get_char_hex(keycode);
-        self.context.held_keys[char as usize] = true;
-        self.context.input = Some(char);
-    }
-
-    pub fn set_keyup(&mut self, keycode: Keycode) {
-        let char = Chip8Emulator::get_char_hex(keycode);
-        self.context.held_keys[char as usize] = false;
-    }
-
-    fn get_char_hex(keycode: Keycode) -> u8 {
-        match keycode {
-            Keycode::Num1 => 0x01,
-            Keycode::Num2 => 0x02,
-            Keycode::Num3 => 0x03,
-            Keycode::Num4 => 0x04,
-            Keycode::Num5 => 0x05,
-            Keycode::Num6 => 0x06,
-            Keycode::Num7 => 0x07,
-            Keycode::Num8 => 0x08,
-            Keycode::Num9 => 0x09,
-            Keycode::Num0 => 0x00,
-            Keycode::A => 0x0A,
-            Keycode::B => 0x0B,
-            Keycode::C => 0x0C,
-            Keycode::D => 0x0D,
-            Keycode::E => 0x0E,
-            Keycode::F => 0x0F,
-            _ => 0x00,
+    // Runs one 60 Hz frame: the instruction budget, or until the next display interrupt with
+    // VIP timing, then a tick of the delay and sound timers. Nothing depends on the host
+    // clock, so the same inputs each frame always give the same state. A stop by the
+    // debugger leaves the rest of the frame for the next call.
+    pub fn tick_frame(&mut self) -> Result<FrameOutcome, Chip8Error> {
+        let frame = self.frame;
+        while self.frame == frame {
+            if let Some(reason) = self.check_breakpoints() {
+                return Ok(FrameOutcome::Stopped(reason));
+            }
+            if self.step_instruction()? == StepOutcome::Exit {
+                return Ok(FrameOutcome::Exit);
+            }
         }
+        Ok(FrameOutcome::Complete)
     }
 
-    pub fn audio(&self, audio_queue: &AudioQueue<i16>) {
-        if self.context.sound == 0 {
-            audio_queue.clear();
-            return;
+    // Runs a single instruction as part of the current frame, which ends once its
+    // instructions ran or with VIP timing at the display interrupt. A waiting instruction
+    // ends the frame early, it would only repeat until then.
+    pub fn step_instruction(&mut self) -> Result<StepOutcome, Chip8Error> {
+        let vip_frame = self.vip_frame();
+        let outcome = self.execute_instruction()?;
+        self.instructions += 1;
+
+        let frame_done = match self.timing {
+            Timing::Fixed => {
+                self.frame_instructions += 1;
+                let done = outcome == StepOutcome::Waiting
+                    || self.frame_instructions >= self.instructions_per_frame;
+                if done {
+                    self.context.tick_timers();
+                }
+                done
+            }
+            // The timers ticked in the display interrupt that ends the frame
+            Timing::CosmacVip => self.vip_frame() != vip_frame,
+        };
+        if frame_done {
+            self.frame += 1;
+            self.frame_instructions = 0;
         }
+        Ok(outcome)
+    }
 
-        let sample_rate = 44100.0;
-        let duration_secs = 1.0;
-        let num_samples = (sample_rate * duration_secs) as usize;
-        let frequency = 440.0; // Frequency of the beep (A4 note)
-        let amplitude = 30000.0; // Amplitude: keep it below 32767 for i16
-
-        let mut beep: Vec<i16> = Vec::with_capacity(num_samples);
-        for i in 0..num_samples {
-            let time = i as f32 / sample_rate;
-            // Calculate the sine wave sample for the given time
-            let sample = (amplitude * (2.0 * PI * frequency * time).sin()) as i16;
-            beep.push(sample);
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    // Keys are the CHIP-8 keypad indices 0x0 to 0xF
+    pub fn set_keydown(&mut self, key: u8) {
+        if let Some(held) = self.context.held_keys.get_mut(key as usize) {
+            *held = true;
+            self.record(key, true);
+        }
+    }
+
+    pub fn set_keyup(&mut self, key: u8) {
+        if let Some(held) = self.context.held_keys.get_mut(key as usize) {
+            *held = false;
+            self.record(key, false);
         }
+    }
+
+    fn record(&mut self, key: u8, down: bool) {
+        if let Some(movie) = self.recording.as_mut() {
+            movie.events.push(MovieEvent {
+                frame: self.frame,
+                key,
+                down,
+            });
+        }
+    }
+
+    pub fn sound(&self) -> Sound {
+        Sound {
+            playing: self.context.sound > 0,
+            pattern: self.context.audio_pattern,
+            pitch: self.context.pitch,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{assembler::assemble, headless::HeadlessRunner};
+
+    const LOOP: &str = "loop:\nADD V0, 1\nJP loop";
+
+    fn emulator(source: &str, timing: Timing) -> Chip8Emulator {
+        let mut chip8 = Chip8Emulator::new(EmulatorMode::Run);
+        chip8.load_rom(&assemble(source).unwrap()).unwrap();
+        chip8.timing = timing;
+        chip8.instructions_per_frame = 4;
+        chip8.context.delay = 10;
+        chip8
+    }
+
+    #[test]
+    fn steps_end_frames_like_running() {
+        let mut chip8 = emulator(LOOP, Timing::Fixed);
+        for _ in 0..3 {
+            chip8.step_instruction().unwrap();
+        }
+        assert_eq!((chip8.frame(), chip8.context.delay), (0, 10));
+        chip8.step_instruction().unwrap();
+        assert_eq!((chip8.frame(), chip8.context.delay), (1, 9));
+
+        let mut running = emulator(LOOP, Timing::Fixed);
+        running.tick_frame().unwrap();
+        assert!(running.save_state() == chip8.save_state());
+    }
+
+    #[test]
+    fn waiting_ends_the_frame() {
+        let mut chip8 = emulator("LD V0, K", Timing::Fixed);
+        assert_eq!(chip8.step_instruction(), Ok(StepOutcome::Waiting));
+        assert_eq!((chip8.frame(), chip8.context.delay), (1, 9));
+    }
+
+    #[test]
+    fn steps_reach_the_display_interrupt_with_vip_timing() {
+        let mut chip8 = emulator(LOOP, Timing::CosmacVip);
+        while chip8.frame() == 0 {
+            chip8.step_instruction().unwrap();
+        }
+        assert_eq!((chip8.vip_frame(), chip8.context.delay), (1, 9));
+
+        let mut running = emulator(LOOP, Timing::CosmacVip);
+        running.tick_frame().unwrap();
+        assert!(running.save_state() == chip8.save_state());
+    }
+
+    #[test]
+    fn stopped_frames_finish_when_resumed() {
+        let mut chip8 = emulator(LOOP, Timing::Fixed);
+        chip8.debugger.breakpoints.insert(0x202);
+        assert!(matches!(chip8.tick_frame(), Ok(FrameOutcome::Stopped(_))));
+        assert_eq!(chip8.frame(), 0);
+
+        chip8.continue_running();
+        chip8.debugger.breakpoints.clear();
+        assert_eq!(chip8.tick_frame(), Ok(FrameOutcome::Complete));
+        assert_eq!(
+            (chip8.frame(), chip8.instructions(), chip8.context.delay),
+            (1, 4, 9)
+        );
+    }
 
-        let _ = audio_queue.queue_audio(&beep);
+    #[test]
+    fn headless_runs_count_frames() {
+        let mut runner = HeadlessRunner::new(emulator(LOOP, Timing::Fixed));
+        runner.run(None, Some(5));
+        assert_eq!((runner.frame, runner.cycles), (5, 20));
+        assert_eq!((runner.chip8.frame(), runner.chip8.context.delay), (5, 5));
     }
 }
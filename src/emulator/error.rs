@@ -0,0 +1,92 @@
+use std::{error::Error, fmt};
+
+// Faults raised while executing a program. `pc` is the address of the faulting instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Chip8Error {
+    // Subroutine call with all 16 stack entries in use
+    StackOverflow {
+        pc: usize,
+        opcode: u16,
+    },
+    // Return with an empty stack
+    StackUnderflow {
+        pc: usize,
+        opcode: u16,
+    },
+    // The program counter left memory, so no instruction could be fetched
+    PcOutOfBounds {
+        pc: usize,
+    },
+    // Instruction accessed memory past the end of RAM
+    MemoryOutOfBounds {
+        pc: usize,
+        opcode: u16,
+        address: usize,
+    },
+    UnknownOpcode {
+        pc: usize,
+        opcode: u16,
+    },
+}
+
+impl Chip8Error {
+    pub fn pc(&self) -> usize {
+        match self {
+            Chip8Error::StackOverflow { pc, .. }
+            | Chip8Error::StackUnderflow { pc, .. }
+            | Chip8Error::PcOutOfBounds { pc }
+            | Chip8Error::MemoryOutOfBounds { pc, .. }
+            | Chip8Error::UnknownOpcode { pc, .. } => *pc,
+        }
+    }
+
+    pub fn opcode(&self) -> Option<u16> {
+        match self {
+            Chip8Error::StackOverflow { opcode, .. }
+            | Chip8Error::StackUnderflow { opcode, .. }
+            | Chip8Error::MemoryOutOfBounds { opcode, .. }
+            | Chip8Error::UnknownOpcode { opcode, .. } => Some(*opcode),
+            Chip8Error::PcOutOfBounds { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::StackOverflow { pc, opcode } => {
+                write!(f, "Stack overflow at {:#05x} ({:04x})", pc, opcode)
+            }
+            Chip8Error::StackUnderflow { pc, opcode } => {
+                write!(f, "Stack underflow at {:#05x} ({:04x})", pc, opcode)
+            }
+            Chip8Error::PcOutOfBounds { pc } => {
+                write!(f, "Program counter out of memory at {:#05x}", pc)
+            }
+            Chip8Error::MemoryOutOfBounds {
+                pc,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "Memory access out of bounds at {:#05x} ({:04x}): address {:#06x}",
+                pc, opcode, address
+            ),
+            Chip8Error::UnknownOpcode { pc, opcode } => {
+                write!(f, "Unknown opcode at {:#05x}: {:04x}", pc, opcode)
+            }
+        }
+    }
+}
+
+impl Error for Chip8Error {}
+
+// What happened when executing a single instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    Continue,
+    // Blocked on input or vertical blank, the instruction will run again
+    Waiting,
+    // The program executed the SUPER-CHIP exit instruction
+    Exit,
+}
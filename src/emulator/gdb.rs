@@ -0,0 +1,706 @@
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+};
+
+use super::{
+    debugger::{Access, StopReason, Watchpoint},
+    emulator::{Chip8Emulator, EmulatorMode},
+};
+
+// Register numbers in g/G/p/P packets. 16 bit registers are sent big-endian like the rest
+// of CHIP-8.
+const REGISTER_I: usize = 16;
+const REGISTER_PC: usize = 17;
+const REGISTER_SP: usize = 18;
+const REGISTER_DT: usize = 19;
+const REGISTER_ST: usize = 20;
+const REGISTER_COUNT: usize = 21;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+// The PacketSize given in qSupported
+const MAX_PACKET_SIZE: usize = 0x1000;
+
+// Signals in stop replies
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+// GDB remote serial protocol server for one client at a time. Execution control goes
+// through the debugger, so breakpoints set here and in the REPL are the same.
+#[derive(Debug)]
+pub struct GdbStub {
+    listener: TcpListener,
+    client: Option<Client>,
+}
+
+#[derive(Debug)]
+struct Client {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    no_ack: bool,
+    // A continue or step is in progress, the client waits for a stop reply
+    running: bool,
+}
+
+impl GdbStub {
+    // Listens on localhost only, the protocol has no authentication
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        Ok(GdbStub {
+            listener,
+            client: None,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.client.is_some()
+    }
+
+    // Accepts a client and handles its packets without blocking, call this from the
+    // emulator loop. Also reports when a running program stopped.
+    pub fn poll(&mut self, chip8: &mut Chip8Emulator) {
+        if self.client.is_none()
+            && let Ok((stream, _)) = self.listener.accept()
+            && stream.set_nonblocking(true).is_ok()
+            // Acks and replies are separate small writes, don't hold them back
+            && stream.set_nodelay(true).is_ok()
+        {
+            // The client expects a stopped target when attaching
+            chip8.pause();
+            self.client = Some(Client {
+                stream,
+                buffer: Vec::new(),
+                no_ack: false,
+                running: false,
+            });
+        }
+
+        let Some(client) = self.client.as_mut() else {
+            return;
+        };
+
+        let result = client
+            .receive()
+            .and_then(|connected| {
+                if connected {
+                    client.handle_packets(chip8)
+                } else {
+                    Ok(false)
+                }
+            })
+            .and_then(|connected| {
+                if connected && client.running && !matches!(chip8.mode, EmulatorMode::Run) {
+                    client.running = false;
+                    client.send(&stop_reply(chip8))?;
+                }
+                Ok(connected)
+            });
+
+        // Let the program run on without the debugger
+        if !matches!(result, Ok(true)) {
+            self.client = None;
+            if let EmulatorMode::Step = chip8.mode {
+                chip8.continue_running();
+            }
+        }
+    }
+}
+
+enum Reply {
+    Packet(String),
+    // Continue and step reply once the program stops
+    Deferred,
+    // Detach and kill end the session, detach acknowledges first
+    Detach(Option<String>),
+}
+
+impl Client {
+    // Reads whatever has arrived, false once the client disconnected
+    fn receive(&mut self) -> io::Result<bool> {
+        let mut chunk = [0; 4096];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Ok(false),
+                Ok(len) => self.buffer.extend_from_slice(&chunk[..len]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(true),
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    // Handles every complete packet in the buffer, false when the client detached
+    fn handle_packets(&mut self, chip8: &mut Chip8Emulator) -> io::Result<bool> {
+        loop {
+            let Some(start) = self.buffer.iter().position(|&b| b == b'$' || b == 0x03) else {
+                self.buffer.clear();
+                return Ok(true);
+            };
+
+            // Ctrl-C interrupts a running program
+            if self.buffer[start] == 0x03 {
+                self.buffer.drain(..=start);
+                chip8.pause();
+                continue;
+            }
+
+            // Packets are $data#checksum
+            let Some(end) = self.buffer[start..].iter().position(|&b| b == b'#') else {
+                // Nothing sends packets this long, drop the garbage rather than keep it
+                if self.buffer.len() - start > MAX_PACKET_SIZE {
+                    self.buffer.clear();
+                }
+                return Ok(true);
+            };
+            let end = start + end;
+            let (Some(data), Some(checksum)) = (
+                self.buffer.get(start + 1..end),
+                self.buffer.get(end + 1..end + 3),
+            ) else {
+                return Ok(true);
+            };
+
+            let data = data.to_vec();
+            let checksum = std::str::from_utf8(checksum)
+                .ok()
+                .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            self.buffer.drain(..end + 3);
+
+            if !self.no_ack {
+                if checksum != Some(checksum_of(&data)) {
+                    self.stream.write_all(b"-")?;
+                    continue;
+                }
+                self.stream.write_all(b"+")?;
+            }
+
+            let packet = String::from_utf8_lossy(&data).into_owned();
+            match self.handle_packet(&packet, chip8) {
+                Reply::Packet(reply) => self.send(&reply)?,
+                Reply::Deferred => {}
+                Reply::Detach(reply) => {
+                    if let Some(reply) = reply {
+                        self.send(&reply)?;
+                    }
+                    return Ok(false);
+                }
+            }
+        }
+    }
+
+    fn handle_packet(&mut self, packet: &str, chip8: &mut Chip8Emulator) -> Reply {
+        let (command, args) = packet.split_at_checked(1).unwrap_or((packet, ""));
+        let reply = match command {
+            "?" => stop_reply(chip8),
+            "g" => (0..REGISTER_COUNT)
+                .map(|register| read_register(chip8, register).unwrap_or_default())
+                .collect(),
+            "G" => write_registers(chip8, args).unwrap_or_else(|| "E01".to_string()),
+            "p" => usize::from_str_radix(args, 16)
+                .ok()
+                .and_then(|register| read_register(chip8, register))
+                .unwrap_or_else(|| "E01".to_string()),
+            "P" => args
+                .split_once('=')
+                .and_then(|(register, value)| {
+                    let register = usize::from_str_radix(register, 16).ok()?;
+                    write_register(chip8, register, &decode_hex(value)?)
+                })
+                .map_or_else(|| "E01".to_string(), |_| "OK".to_string()),
+            "m" => parse_range(args)
+                .and_then(|(address, len)| read_memory(chip8, address, len))
+                .unwrap_or_else(|| "E01".to_string()),
+            "M" => args
+                .split_once(':')
+                .and_then(|(range, data)| {
+                    let (address, len) = parse_range(range)?;
+                    let data = decode_hex(data).filter(|data| data.len() == len)?;
+                    write_memory(chip8, address, &data)
+                })
+                .map_or_else(|| "E01".to_string(), |_| "OK".to_string()),
+            "Z" | "z" => set_breakpoint(chip8, command == "Z", args)
+                .map_or_else(|| "E01".to_string(), |_| "OK".to_string()),
+            "c" | "s" => {
+                if let Ok(address) = usize::from_str_radix(args, 16) {
+                    chip8.context.pc = address;
+                }
+                // A halted emulator cannot resume, the stop reply reports the fault again
+                if !matches!(chip8.mode, EmulatorMode::Halted) {
+                    if command == "c" {
+                        chip8.continue_running();
+                    } else {
+                        chip8.step_into();
+                    }
+                }
+                self.running = true;
+                return Reply::Deferred;
+            }
+            "D" => return Reply::Detach(Some("OK".to_string())),
+            "k" => return Reply::Detach(None),
+            "H" => "OK".to_string(),
+            "q" | "Q" | "v" => query(&mut self.no_ack, packet),
+            _ => String::new(),
+        };
+        Reply::Packet(reply)
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+
+        // Replies are small, write them out in one go
+        self.stream.set_nonblocking(false)?;
+        let result = self.stream.write_all(packet.as_bytes());
+        self.stream.set_nonblocking(true)?;
+        result
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &b| sum.wrapping_add(b))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|at| u8::from_str_radix(text.get(at..at + 2)?, 16).ok())
+        .collect()
+}
+
+// addr,len in hex
+fn parse_range(text: &str) -> Option<(usize, usize)> {
+    let (address, len) = text.split_once(',')?;
+    Some((
+        usize::from_str_radix(address, 16).ok()?,
+        usize::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+fn stop_reply(chip8: &Chip8Emulator) -> String {
+    if let EmulatorMode::Halted = chip8.mode {
+        return format!("S{:02x}", SIGSEGV);
+    }
+    match chip8.debugger.last_stop() {
+        Some(StopReason::Watchpoint { address, access }) => {
+            let kind = match access {
+                Access::Write => "watch",
+                Access::Read => "rwatch",
+                Access::ReadWrite => "awatch",
+            };
+            format!("T{:02x}{}:{:x};", SIGTRAP, kind, address)
+        }
+        Some(_) => format!("S{:02x}", SIGTRAP),
+        None => format!("S{:02x}", SIGINT),
+    }
+}
+
+fn read_register(chip8: &Chip8Emulator, register: usize) -> Option<String> {
+    let context = &chip8.context;
+    let bytes = match register {
+        0..=15 => vec![context.v[register]],
+        REGISTER_I => context.i.to_be_bytes().to_vec(),
+        REGISTER_PC => (context.pc as u16).to_be_bytes().to_vec(),
+        REGISTER_SP => vec![context.sp as u8],
+        REGISTER_DT => vec![context.delay],
+        REGISTER_ST => vec![context.sound],
+        _ => return None,
+    };
+    Some(encode_hex(&bytes))
+}
+
+fn register_size(register: usize) -> usize {
+    match register {
+        REGISTER_I | REGISTER_PC => 2,
+        _ => 1,
+    }
+}
+
+fn write_register(chip8: &mut Chip8Emulator, register: usize, bytes: &[u8]) -> Option<()> {
+    if bytes.len() != register_size(register) {
+        return None;
+    }
+    let context = &mut chip8.context;
+    match register {
+        0..=15 => context.v[register] = bytes[0],
+        REGISTER_I => context.i = u16::from_be_bytes([bytes[0], bytes[1]]),
+        REGISTER_PC => context.pc = u16::from_be_bytes([bytes[0], bytes[1]]) as usize,
+        REGISTER_SP if (bytes[0] as usize) <= context.stack.len() => context.sp = bytes[0] as usize,
+        REGISTER_DT => context.delay = bytes[0],
+        REGISTER_ST => context.sound = bytes[0],
+        _ => return None,
+    }
+    Some(())
+}
+
+fn write_registers(chip8: &mut Chip8Emulator, hex: &str) -> Option<String> {
+    let bytes = decode_hex(hex)?;
+    let mut offset = 0;
+    for register in 0..REGISTER_COUNT {
+        let size = register_size(register);
+        write_register(chip8, register, bytes.get(offset..offset + size)?)?;
+        offset += size;
+    }
+    Some("OK".to_string())
+}
+
+fn read_memory(chip8: &Chip8Emulator, address: usize, len: usize) -> Option<String> {
+    let memory = &chip8.context.memory;
+    let end = address.checked_add(len)?.min(memory.len());
+    (address < end).then(|| encode_hex(&memory[address..end]))
+}
+
+fn write_memory(chip8: &mut Chip8Emulator, address: usize, data: &[u8]) -> Option<()> {
+    chip8
+        .context
+        .memory
+        .get_mut(address..address.checked_add(data.len())?)?
+        .copy_from_slice(data);
+    Some(())
+}
+
+// Z/z type,addr,kind. Software and hardware breakpoints are the same thing here, for
+// watchpoints kind is the number of bytes watched.
+fn set_breakpoint(chip8: &mut Chip8Emulator, insert: bool, args: &str) -> Option<()> {
+    let mut fields = args.split(',');
+    let kind = fields.next()?;
+    let address = usize::from_str_radix(fields.next()?, 16).ok()?;
+    let len = usize::from_str_radix(fields.next()?, 16).ok()?;
+
+    let access = match kind {
+        "0" | "1" => {
+            if insert {
+                chip8.debugger.breakpoints.insert(address);
+            } else {
+                chip8.debugger.breakpoints.remove(&address);
+            }
+            return Some(());
+        }
+        "2" => Access::Write,
+        "3" => Access::Read,
+        "4" => Access::ReadWrite,
+        _ => return None,
+    };
+
+    let watchpoint = Watchpoint {
+        range: address..address.checked_add(len.max(1))?,
+        access,
+    };
+    if insert {
+        chip8.debugger.watchpoints.push(watchpoint);
+    } else {
+        chip8
+            .debugger
+            .watchpoints
+            .retain(|other| *other != watchpoint);
+    }
+    Some(())
+}
+
+// General queries and v packets. Anything unknown gets the empty "not supported" reply.
+fn query(no_ack: &mut bool, packet: &str) -> String {
+    if packet.starts_with("qSupported") {
+        return format!(
+            "PacketSize={:x};QStartNoAckMode+;qXfer:features:read+",
+            MAX_PACKET_SIZE
+        );
+    }
+    if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+        let Some((offset, len)) = parse_range(range) else {
+            return "E01".to_string();
+        };
+        let xml = TARGET_XML.as_bytes();
+        let start = offset.min(xml.len());
+        let end = start.saturating_add(len).min(xml.len());
+        let marker = if end == xml.len() { 'l' } else { 'm' };
+        return format!("{}{}", marker, String::from_utf8_lossy(&xml[start..end]));
+    }
+
+    match packet {
+        "QStartNoAckMode" => {
+            *no_ack = true;
+            "OK".to_string()
+        }
+        "qAttached" => "1".to_string(),
+        "qC" => "QC1".to_string(),
+        "qfThreadInfo" => "m1".to_string(),
+        "qsThreadInfo" => "l".to_string(),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::emulator::{assembler::assemble, emulator::FrameOutcome};
+
+    // A stub with a connected client, talking through a real socket
+    struct Session {
+        stub: GdbStub,
+        stream: TcpStream,
+        chip8: Chip8Emulator,
+        received: Vec<u8>,
+    }
+
+    impl Session {
+        fn new(source: &str) -> Self {
+            let mut chip8 = Chip8Emulator::new(EmulatorMode::Run);
+            chip8.load_rom(&assemble(source).unwrap()).unwrap();
+            let mut stub = GdbStub::listen(0).unwrap();
+            let stream = TcpStream::connect(stub.local_addr().unwrap()).unwrap();
+            stream.set_nonblocking(true).unwrap();
+
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while !stub.is_attached() {
+                assert!(Instant::now() < deadline, "client was not accepted");
+                thread::sleep(Duration::from_millis(1));
+                stub.poll(&mut chip8);
+            }
+            Session {
+                stub,
+                stream,
+                chip8,
+                received: Vec::new(),
+            }
+        }
+
+        fn send_raw(&mut self, bytes: &[u8]) {
+            self.stream.write_all(bytes).unwrap();
+        }
+
+        fn send(&mut self, packet: &str) {
+            let checksum = checksum_of(packet.as_bytes());
+            self.send_raw(format!("${}#{:02x}", packet, checksum).as_bytes());
+        }
+
+        // Polls the stub until what it sent is complete
+        fn receive_until(&mut self, complete: impl Fn(&str) -> bool) -> String {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            loop {
+                self.stub.poll(&mut self.chip8);
+                let mut chunk = [0; 4096];
+                match self.stream.read(&mut chunk) {
+                    Ok(len) => self.received.extend_from_slice(&chunk[..len]),
+                    Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+                    Err(error) => panic!("{}", error),
+                }
+
+                let text = String::from_utf8_lossy(&self.received).into_owned();
+                if complete(&text) {
+                    self.received.clear();
+                    return text;
+                }
+                assert!(Instant::now() < deadline, "no reply, got {:?}", text);
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+
+        fn receive_ack(&mut self) -> String {
+            self.receive_until(|text| !text.is_empty())
+        }
+
+        // A packet, after its ack unless acks are off
+        fn receive(&mut self) -> String {
+            self.receive_until(|text| text.rfind('#').is_some_and(|hash| text.len() >= hash + 3))
+        }
+
+        // A request and the data of its reply
+        fn request(&mut self, packet: &str) -> String {
+            self.send(packet);
+            let reply = self.receive();
+            let data = reply
+                .strip_prefix("+$")
+                .and_then(|reply| reply.rsplit_once('#'))
+                .unwrap_or_else(|| panic!("malformed reply {:?}", reply));
+            assert_eq!(format!("{:02x}", checksum_of(data.0.as_bytes())), data.1);
+            data.0.to_string()
+        }
+    }
+
+    #[test]
+    fn registers_are_read_and_written() {
+        let mut session = Session::new("CLS");
+        session.chip8.context.v[1] = 0xAB;
+        session.chip8.context.i = 0x1234;
+
+        let registers = session.request("g");
+        assert_eq!(registers.len(), 2 * (16 + 2 + 2 + 3));
+        assert_eq!(&registers[2..4], "ab");
+        assert_eq!(&registers[32..40], "12340200");
+
+        let mut written = registers.clone();
+        written.replace_range(0..2, "7f");
+        assert_eq!(session.request(&format!("G{}", written)), "OK");
+        assert_eq!(session.chip8.context.v[0], 0x7F);
+        assert_eq!(session.request("G00"), "E01");
+
+        assert_eq!(session.request("p10"), "1234");
+        assert_eq!(session.request("P11=0300"), "OK");
+        assert_eq!(session.chip8.context.pc, 0x300);
+        assert_eq!(session.request("P11=03"), "E01");
+        assert_eq!(session.request("P12=ff"), "E01");
+        assert_eq!(session.request("p15"), "E01");
+    }
+
+    #[test]
+    fn memory_is_read_and_written() {
+        let mut session = Session::new("CLS");
+        assert_eq!(session.request("m200,2"), "00e0");
+        assert_eq!(session.request("M300,2:beef"), "OK");
+        assert_eq!(session.chip8.context.memory[0x300..0x302], [0xBE, 0xEF]);
+
+        // Reads stop at the end of memory, writes past it fail
+        assert_eq!(session.request("mffe,10"), "0000");
+        assert_eq!(session.request("m1000,1"), "E01");
+        assert_eq!(session.request("mffffffffffffffff,2"), "E01");
+        assert_eq!(session.request("Mfff,2:beef"), "E01");
+        assert_eq!(session.request("Mffffffffffffffff,1:be"), "E01");
+        assert_eq!(session.request("M300,2:be"), "E01");
+        assert_eq!(session.request("M300,1:zz"), "E01");
+        assert_eq!(session.request("m300"), "E01");
+    }
+
+    #[test]
+    fn breakpoints_and_watchpoints_are_set_and_removed() {
+        let mut session = Session::new("CLS");
+        assert_eq!(session.request("Z0,204,2"), "OK");
+        assert!(session.chip8.debugger.breakpoints.contains(&0x204));
+        assert_eq!(session.request("z0,204,2"), "OK");
+        assert!(session.chip8.debugger.breakpoints.is_empty());
+
+        assert_eq!(session.request("Z3,300,4"), "OK");
+        assert_eq!(
+            session.chip8.debugger.watchpoints,
+            [Watchpoint {
+                range: 0x300..0x304,
+                access: Access::Read
+            }]
+        );
+        assert_eq!(session.request("z3,300,4"), "OK");
+        assert!(session.chip8.debugger.watchpoints.is_empty());
+
+        assert_eq!(session.request("Z2,ffffffffffffffff,1"), "E01");
+        assert_eq!(session.request("Z4,1,ffffffffffffffff"), "E01");
+        assert_eq!(session.request("Z5,300,1"), "E01");
+        assert_eq!(session.request("Z2,300"), "E01");
+        assert!(session.chip8.debugger.watchpoints.is_empty());
+    }
+
+    #[test]
+    fn continue_and_step_reply_when_stopped() {
+        let mut session = Session::new("LD V0, 1\nLD I, 0x300\nLD [I], V0\nend:\nJP end");
+        assert_eq!(session.request("?"), "S02");
+
+        session.request("Z2,300,1");
+        session.send("c");
+        assert_eq!(session.receive_ack(), "+");
+        assert!(matches!(session.chip8.mode, EmulatorMode::Run));
+        assert!(matches!(
+            session.chip8.tick_frame(),
+            Ok(FrameOutcome::Stopped(_))
+        ));
+        let reply = session.receive();
+        assert!(reply.starts_with("$T05watch:300;#"), "{}", reply);
+        assert_eq!(session.chip8.context.pc, 0x204);
+
+        session.send("s");
+        assert_eq!(session.receive_ack(), "+");
+        session.chip8.tick_frame().unwrap();
+        assert!(session.receive().starts_with("$S05#"));
+        assert_eq!(session.chip8.context.pc, 0x206);
+    }
+
+    #[test]
+    fn malformed_input_is_survived() {
+        let mut session = Session::new("CLS");
+
+        // A bad checksum is asked to be sent again
+        session.send_raw(b"$g#00");
+        assert_eq!(session.receive_ack(), "-");
+        session.send_raw(b"$g#+1");
+        assert_eq!(session.receive_ack(), "-");
+
+        // Garbage before a packet, and a packet split across reads
+        session.send_raw(b"junk$m20");
+        session.stub.poll(&mut session.chip8);
+        session.send_raw(b"0,2#");
+        session.stub.poll(&mut session.chip8);
+        session.send_raw(format!("{:02x}", checksum_of(b"m200,2")).as_bytes());
+        assert!(session.receive().starts_with("+$00e0#"));
+
+        // Empty and unknown packets are unsupported
+        assert_eq!(session.request(""), "");
+        assert_eq!(session.request("X"), "");
+        assert_eq!(session.request("p"), "E01");
+        assert_eq!(session.request("qXfer:features:read:target.xml:zz"), "E01");
+
+        // An endless packet is dropped, and the session carries on
+        session.send_raw(&[b'$'; MAX_PACKET_SIZE + 2]);
+        session.stub.poll(&mut session.chip8);
+        assert_eq!(session.request("m200,1"), "00");
+        assert!(session.stub.is_attached());
+    }
+
+    #[test]
+    fn no_ack_mode_and_detaching() {
+        let mut session = Session::new("CLS");
+        assert!(
+            session
+                .request("qSupported:swbreak+")
+                .starts_with("PacketSize=1000;")
+        );
+        assert_eq!(session.request("QStartNoAckMode"), "OK");
+        session.send("m200,1");
+        assert!(session.receive().starts_with("$00#"));
+
+        session.send("D");
+        assert!(session.receive().starts_with("$OK#"));
+        assert!(!session.stub.is_attached());
+        assert!(matches!(session.chip8.mode, EmulatorMode::Run));
+    }
+}
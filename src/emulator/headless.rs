@@ -0,0 +1,153 @@
+use std::{fmt, fs::File, io, io::BufWriter, path::Path};
+
+use super::{
+    decoder::{Instruction, decode_at},
+    emulator::{Chip8Emulator, FrameOutcome},
+    error::Chip8Error,
+};
+
+// Characters for the bitplane masks in ASCII dumps
+const ASCII_PIXELS: [char; 4] = ['.', '#', '+', '@'];
+
+// Shades of grey for the bitplane masks in PNG dumps
+const PNG_PIXELS: [u8; 4] = [0x00, 0xFF, 0xAA, 0x55];
+
+// A keypad key held down for a number of frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyPress {
+    pub frame: u64,
+    pub key: u8,
+    pub frames: u64,
+}
+
+// Why a headless run ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stop {
+    CycleLimit,
+    FrameLimit,
+    // A jump to itself, the usual way for a program to end
+    Loop { pc: usize },
+    // The SUPER-CHIP exit instruction
+    Exit,
+    Fault(Chip8Error),
+}
+
+// Runs an emulator without a frontend, frame by frame with the emulator's instructions per
+// frame, so runs are repeatable.
+#[derive(Debug)]
+pub struct HeadlessRunner {
+    pub chip8: Chip8Emulator,
+    pub keys: Vec<KeyPress>,
+
+    pub frame: u64,
+    pub cycles: u64,
+}
+
+impl HeadlessRunner {
+    pub fn new(chip8: Chip8Emulator) -> Self {
+        HeadlessRunner {
+            chip8,
+            keys: Vec::new(),
+            frame: 0,
+            cycles: 0,
+        }
+    }
+
+    // Runs whole frames until a limit is reached or the program ends, loops or faults. The
+    // limits are checked between frames, so a run can go up to a frame past max_cycles.
+    pub fn run(&mut self, max_cycles: Option<u64>, max_frames: Option<u64>) -> Stop {
+        loop {
+            if max_frames.is_some_and(|frames| self.frame >= frames) {
+                return Stop::FrameLimit;
+            }
+            if max_cycles.is_some_and(|cycles| self.cycles >= cycles) {
+                return Stop::CycleLimit;
+            }
+            self.press_keys();
+
+            let instructions = self.chip8.instructions();
+            let outcome = self.chip8.tick_frame();
+            self.cycles += self.chip8.instructions() - instructions;
+            match outcome {
+                Ok(FrameOutcome::Exit) => return Stop::Exit,
+                Err(error) => return Stop::Fault(error),
+                Ok(_) => {}
+            }
+            self.frame += 1;
+
+            // Programs end in a jump to itself
+            let pc = self.chip8.context.pc;
+            if decode_at(&self.chip8.context.memory, pc) == Some(Instruction::Jump(pc as u16)) {
+                return Stop::Loop { pc };
+            }
+        }
+    }
+
+    // Presses and releases the scripted keys due this frame
+    fn press_keys(&mut self) {
+        for press in &self.keys {
+            if press.frame == self.frame {
+                self.chip8.set_keydown(press.key);
+            } else if press.frame + press.frames == self.frame {
+                self.chip8.set_keyup(press.key);
+            }
+        }
+    }
+
+    // The screen at the current resolution, one character per pixel
+    pub fn screen_ascii(&self) -> String {
+        let frame_buffer = &self.chip8.context.frame_buffer;
+        let pixels: Vec<char> = frame_buffer
+            .pixels()
+            .map(|pixel| ASCII_PIXELS[pixel as usize & 0b11])
+            .collect();
+
+        pixels
+            .chunks(frame_buffer.width())
+            .map(|row| row.iter().collect::<String>() + "\n")
+            .collect()
+    }
+
+    // Greyscale PNG of the screen, each pixel scaled up to a square of scale pixels
+    pub fn write_png(&self, path: &Path, scale: usize) -> io::Result<()> {
+        let frame_buffer = &self.chip8.context.frame_buffer;
+        let scale = scale.max(1);
+        let (width, height) = (frame_buffer.width(), frame_buffer.height());
+
+        let pixels: Vec<u8> = frame_buffer
+            .pixels()
+            .map(|pixel| PNG_PIXELS[pixel as usize & 0b11])
+            .collect();
+        let mut data = Vec::with_capacity(width * height * scale * scale);
+        for row in pixels.chunks(width) {
+            let scaled: Vec<u8> = row
+                .iter()
+                .flat_map(|&pixel| std::iter::repeat_n(pixel, scale))
+                .collect();
+            for _ in 0..scale {
+                data.extend_from_slice(&scaled);
+            }
+        }
+
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, (width * scale) as u32, (height * scale) as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&data))
+            .map_err(io::Error::other)
+    }
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::CycleLimit => write!(f, "Cycle limit reached"),
+            Stop::FrameLimit => write!(f, "Frame limit reached"),
+            Stop::Loop { pc } => write!(f, "Infinite loop at {:#05x}", pc),
+            Stop::Exit => write!(f, "Program exited"),
+            Stop::Fault(error) => write!(f, "{}", error),
+        }
+    }
+}
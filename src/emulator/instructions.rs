@@ -1,294 +1,722 @@
-use rand::Rng;
+use std::ops::Range;
 
-use crate::emulator::chip8_context::{HEIGHT, WIDTH};
-
-use super::emulator::{Chip8Emulator, FONT_OFFSET};
+use super::{
+    decoder::{Instruction, decode},
+    emulator::{BIG_FONT_OFFSET, Chip8Emulator, FONT_OFFSET},
+    error::{Chip8Error, StepOutcome},
+};
 
 impl Chip8Emulator {
-    pub fn execute_instruction(&mut self) {
-        let (start, end) = self.context.get_next_instruction();
+    pub fn execute_instruction(&mut self) -> Result<StepOutcome, Chip8Error> {
+        let pc = self.context.pc;
+        let (start, end) = self
+            .context
+            .get_next_instruction()
+            .ok_or(Chip8Error::PcOutOfBounds { pc })?;
         let full = ((start as u16) << 8) | (end as u16);
+        let mut outcome = StepOutcome::Continue;
+
+        let instruction = decode(full);
+        let cycles = self.instruction_cycles(instruction);
 
-        let nibble_1 = (full >> 12) & 0xF; // First nibble (high nibble of the high byte)
-        let nibble_2 = (full >> 8) & 0xF; // Second nibble (low nibble of the high byte)
-        let nibble_3 = (full >> 4) & 0xF; // Third nibble (high nibble of the low byte)
-        let nibble_4 = full & 0xF; // Fourth nibble (low nibble of the low byte)
         self.context.increment_pc();
 
-        match (nibble_1, nibble_2, nibble_3, nibble_4) {
+        match instruction {
             // Clear screen
-            (0, 0, 0xE, 0) => {
+            Instruction::Cls => {
                 self.context.frame_buffer.clear();
             }
             // Return from subroutine
-            (0, 0, 0xE, 0xE) => {
-                let ret = self.context.stack_pop();
+            Instruction::Ret => {
+                let ret = self
+                    .context
+                    .stack_pop()
+                    .ok_or(Chip8Error::StackUnderflow { pc, opcode: full })?;
                 self.context.pc = ret as usize;
             }
-            (0, _, _, _) => {}
+            // Scroll down N lines
+            Instruction::ScrollDown(n) => {
+                self.context.frame_buffer.scroll_down(n as usize);
+            }
+            // Scroll up N lines
+            Instruction::ScrollUp(n) => {
+                self.context.frame_buffer.scroll_up(n as usize);
+            }
+            // Scroll right 4 pixels
+            Instruction::ScrollRight => {
+                self.context.frame_buffer.scroll_right(4);
+            }
+            // Scroll left 4 pixels
+            Instruction::ScrollLeft => {
+                self.context.frame_buffer.scroll_left(4);
+            }
+            // Exit the interpreter, stay on this instruction
+            Instruction::Exit => {
+                self.context.decrement_pc();
+                outcome = StepOutcome::Exit;
+            }
+            // Disable hires mode
+            Instruction::Lores => {
+                self.context.frame_buffer.set_hires(false);
+            }
+            // Enable hires mode
+            Instruction::Hires => {
+                self.context.frame_buffer.set_hires(true);
+            }
+            Instruction::Sys(_) => {}
             // Jump to NNN
-            (1, _, _, _) => {
-                let masked = full & 0x0FFF;
-                self.context.pc = masked as usize;
+            Instruction::Jump(nnn) => {
+                self.context.pc = nnn as usize;
             }
             // Jump to subroutine
-            (2, _, _, _) => {
-                let masked = full & 0x0FFF;
-                self.context.stack_push(self.context.pc as u16);
-                self.context.pc = masked as usize;
-            }
-            // Skip next if nn != vx
-            (3, _, _, _) => {
-                let nn = (full & 0x00FF) as u8;
-                let vx = self.context.v[nibble_2 as usize];
-                if nn == vx {
-                    self.context.increment_pc();
+            Instruction::Call(nnn) => {
+                self.context
+                    .stack_push(self.context.pc as u16)
+                    .ok_or(Chip8Error::StackOverflow { pc, opcode: full })?;
+                self.context.pc = nnn as usize;
+            }
+            // Skip next if nn == vx
+            Instruction::SkipEqImm(x, nn) => {
+                if self.context.v[x as usize] == nn {
+                    self.context.skip_instruction();
                 }
             }
             // Skip next if nn != vx
-            (4, _, _, _) => {
-                let nn = (full & 0x00FF) as u8;
-                let vx = self.context.v[nibble_2 as usize];
-                if nn != vx {
-                    self.context.increment_pc();
+            Instruction::SkipNeImm(x, nn) => {
+                if self.context.v[x as usize] != nn {
+                    self.context.skip_instruction();
                 }
             }
             // Skip next if vx == vy
-            (5, _, _, _) => {
-                let vx = self.context.v[nibble_2 as usize];
-                let vy = self.context.v[nibble_3 as usize];
-
-                if vx == vy {
-                    self.context.increment_pc();
+            Instruction::SkipEq(x, y) => {
+                if self.context.v[x as usize] == self.context.v[y as usize] {
+                    self.context.skip_instruction();
+                }
+            }
+            // Store vx to vy in memory starting at I
+            Instruction::SaveRange(x, y) => {
+                let (x, y) = (x as usize, y as usize);
+                let range = self.memory_range(pc, full, x.abs_diff(y) + 1)?;
+                for (address, reg) in range.zip(Chip8Emulator::register_range(x, y)) {
+                    self.context.memory[address] = self.context.v[reg];
+                }
+            }
+            // Load vx to vy from memory starting at I
+            Instruction::LoadRange(x, y) => {
+                let (x, y) = (x as usize, y as usize);
+                let range = self.memory_range(pc, full, x.abs_diff(y) + 1)?;
+                for (address, reg) in range.zip(Chip8Emulator::register_range(x, y)) {
+                    self.context.v[reg] = self.context.memory[address];
                 }
             }
             // Set vx to NN
-            (6, _, _, _) => {
-                self.context.v[nibble_2 as usize] = end;
+            Instruction::LoadImm(x, nn) => {
+                self.context.v[x as usize] = nn;
             }
-            // Add vx to NN
-            (7, _, _, _) => {
-                self.context.v[nibble_2 as usize] =
-                    self.context.v[nibble_2 as usize].wrapping_add(end);
+            // Add NN to vx
+            Instruction::AddImm(x, nn) => {
+                self.context.v[x as usize] = self.context.v[x as usize].wrapping_add(nn);
             }
             // Set vx to vy
-            (8, _, _, 0) => {
-                let vy = self.context.v[nibble_3 as usize];
-                self.context.v[nibble_2 as usize] = vy;
-            }
-            (8, _, _, 1) => {
-                let vx = self.context.v[nibble_2 as usize];
-                let vy = self.context.v[nibble_3 as usize];
-                self.context.v[nibble_2 as usize] = vx | vy;
-            }
-            (8, _, _, 2) => {
-                let vx = self.context.v[nibble_2 as usize];
-                let vy = self.context.v[nibble_3 as usize];
-                self.context.v[nibble_2 as usize] = vx & vy;
-            }
-            (8, _, _, 3) => {
-                let vx = self.context.v[nibble_2 as usize];
-                let vy = self.context.v[nibble_3 as usize];
-                self.context.v[nibble_2 as usize] = vx ^ vy;
-            }
-            (8, _, _, 4) => {
-                let vx = self.context.v[nibble_2 as usize];
-                let vy = self.context.v[nibble_3 as usize];
-                let (res, overflow) = vx.overflowing_add(vy);
-                self.context.v[nibble_2 as usize] = res;
-                if overflow {
-                    self.context.v[0x0F] = 1;
-                }
+            Instruction::Move(x, y) => {
+                self.context.v[x as usize] = self.context.v[y as usize];
             }
-            (8, _, _, 5) => {
-                let vx = self.context.v[nibble_2 as usize];
-                let vy = self.context.v[nibble_3 as usize];
-                let (res, overflow) = vx.overflowing_sub(vy);
-                self.context.v[nibble_2 as usize] = res;
-
-                self.context.v[0x0F] = 1;
-
-                if overflow {
+            Instruction::Or(x, y) => {
+                self.context.v[x as usize] |= self.context.v[y as usize];
+                if self.quirks.vf_reset {
                     self.context.v[0x0F] = 0;
                 }
             }
-            (8, _, _, 6) => {
-                let x = nibble_2 as usize;
-                let y = nibble_3 as usize;
-                self.context.v[x] = self.context.v[y];
-
-                self.context.v[0x0F] = self.context.v[x] & 0b10000000;
-                self.context.v[x] <<= 1;
+            Instruction::And(x, y) => {
+                self.context.v[x as usize] &= self.context.v[y as usize];
+                if self.quirks.vf_reset {
+                    self.context.v[0x0F] = 0;
+                }
             }
-            (8, _, _, 7) => {
-                let vx = self.context.v[nibble_2 as usize];
-                let vy = self.context.v[nibble_3 as usize];
-                let (res, overflow) = vy.overflowing_sub(vx);
-                self.context.v[nibble_2 as usize] = res;
-
-                self.context.v[0x0F] = 1;
-
-                if overflow {
+            Instruction::Xor(x, y) => {
+                self.context.v[x as usize] ^= self.context.v[y as usize];
+                if self.quirks.vf_reset {
                     self.context.v[0x0F] = 0;
                 }
             }
-            (8, _, _, 0xE) => {
-                let x = nibble_2 as usize;
-                let y = nibble_3 as usize;
-                self.context.v[x] = self.context.v[y];
-                self.context.v[0x0F] = self.context.v[x] & 0b00000001;
-                self.context.v[x] >>= 1;
+            // Set vx to vx + vy, VF is 1 on carry. Like the rest of the group, VF is
+            // written after the result so that with VF as VX it ends up holding the flag.
+            Instruction::Add(x, y) => {
+                let vx = self.context.v[x as usize];
+                let vy = self.context.v[y as usize];
+                let (res, carry) = vx.overflowing_add(vy);
+                self.context.v[x as usize] = res;
+                self.context.v[0x0F] = carry as u8;
+            }
+            // Set vx to vx - vy, VF is 1 when there was no borrow
+            Instruction::Sub(x, y) => {
+                let vx = self.context.v[x as usize];
+                let vy = self.context.v[y as usize];
+                let (res, borrow) = vx.overflowing_sub(vy);
+                self.context.v[x as usize] = res;
+                self.context.v[0x0F] = !borrow as u8;
+            }
+            // Shift right, VF gets the bit shifted out
+            Instruction::ShiftRight(x, y) => {
+                let value = self.shift_operand(x, y);
+                self.context.v[x as usize] = value >> 1;
+                self.context.v[0x0F] = value & 1;
+            }
+            // Set vx to vy - vx, VF is 1 when there was no borrow
+            Instruction::SubReverse(x, y) => {
+                let vx = self.context.v[x as usize];
+                let vy = self.context.v[y as usize];
+                let (res, borrow) = vy.overflowing_sub(vx);
+                self.context.v[x as usize] = res;
+                self.context.v[0x0F] = !borrow as u8;
+            }
+            // Shift left, VF gets the bit shifted out
+            Instruction::ShiftLeft(x, y) => {
+                let value = self.shift_operand(x, y);
+                self.context.v[x as usize] = value << 1;
+                self.context.v[0x0F] = value >> 7;
             }
             // Skip next if vx != vy
-            (9, _, _, _) => {
-                let vx = self.context.v[nibble_2 as usize];
-                let vy = self.context.v[nibble_3 as usize];
-
-                if vx != vy {
-                    self.context.increment_pc();
+            Instruction::SkipNe(x, y) => {
+                if self.context.v[x as usize] != self.context.v[y as usize] {
+                    self.context.skip_instruction();
                 }
             }
             // Set I to NNN
-            (0xA, _, _, _) => {
-                let masked = full & 0x0FFF;
-                self.context.i = masked;
+            Instruction::LoadI(nnn) => {
+                self.context.i = nnn;
             }
-            // Jump with offset
-            (0xB, _, _, _) => {
-                let nnn = full & 0x0FFF;
-                let v0 = self.context.v[0] as u16;
-                self.context.pc = (nnn + v0) as usize;
+            // Jump with offset, from V0 or from VX when the jump quirk is set
+            Instruction::JumpOffset(nnn) => {
+                let offset = if self.quirks.jump {
+                    self.context.v[(nnn >> 8) as usize] as u16
+                } else {
+                    self.context.v[0] as u16
+                };
+                self.context.pc = (nnn + offset) as usize;
             }
             // Random
-            (0xC, _, _, _) => {
-                let nn = (full & 0x00FF) as u8;
-                let mut rng = rand::thread_rng();
-                let generated: u8 = rng.r#gen();
-                self.context.v[nibble_2 as usize] = generated & nn;
-            }
-            (0xE, _, 9, 0xE) => {
-                let x = self.context.v[nibble_2 as usize];
-                if self.context.held_keys[x as usize] {
-                    self.context.pc += 2;
+            Instruction::Random(x, nn) => {
+                let generated = self.random.next_byte(&self.context.memory);
+                self.context.v[x as usize] = generated & nn;
+            }
+            // Draw to screen, at most once per frame when waiting for vertical blank
+            Instruction::Draw(x, y, n) => {
+                if self.quirks.display_wait && !self.context.vblank {
+                    self.context.decrement_pc();
+                    outcome = StepOutcome::Waiting;
+                } else {
+                    self.context.vblank = false;
+                    self.draw_sprite(pc, full, x as usize, y as usize, n as usize)?;
                 }
             }
-            (0xE, _, 0xA, 1) => {
-                let x = self.context.v[nibble_2 as usize];
-                if !self.context.held_keys[x as usize] {
-                    self.context.pc += 2;
+            Instruction::SkipKey(x) => {
+                let key = self.context.v[x as usize] & 0xF;
+                if self.context.held_keys[key as usize] {
+                    self.context.skip_instruction();
                 }
             }
-            // Wait for input and place in vx
-            (0xF, _, 0, 0xA) => {
-                let x = nibble_2 as usize;
-
-                if let Some(ch) = self.context.input {
-                    self.context.v[x] = ch;
-                } else {
-                    self.context.decrement_pc();
+            Instruction::SkipNotKey(x) => {
+                let key = self.context.v[x as usize] & 0xF;
+                if !self.context.held_keys[key as usize] {
+                    self.context.skip_instruction();
                 }
             }
-            // Set delay timer
-            (0xF, _, 1, 5) => {
-                let x = nibble_2 as usize;
-                self.context.delay = self.context.v[x];
+            // Set I to the 16 bit address in the next word
+            Instruction::LoadLongI(_) => {
+                let (high, low) =
+                    self.context
+                        .get_next_instruction()
+                        .ok_or(Chip8Error::MemoryOutOfBounds {
+                            pc,
+                            opcode: full,
+                            address: self.context.pc,
+                        })?;
+                self.context.i = ((high as u16) << 8) | (low as u16);
+                self.context.increment_pc();
+            }
+            // Select bitplanes
+            Instruction::Plane(n) => {
+                self.context.frame_buffer.select_planes(n);
+            }
+            // Load the audio pattern from I
+            Instruction::Audio => {
+                let range = self.memory_range(pc, full, 16)?;
+                self.context
+                    .audio_pattern
+                    .copy_from_slice(&self.context.memory[range]);
             }
             // Set vx to delay
-            (0xF, _, 0, 7) => {
-                let x = nibble_2 as usize;
-                self.context.v[x] = self.context.delay;
+            Instruction::LoadDelay(x) => {
+                self.context.v[x as usize] = self.context.delay;
+            }
+            // Wait for a key to be pressed and released, like the COSMAC VIP, and place it
+            // in vx. The timers keep running while waiting.
+            Instruction::WaitKey(x) => match self.context.wait_key {
+                Some(key) if !self.context.held_keys[key as usize] => {
+                    self.context.v[x as usize] = key;
+                    self.context.wait_key = None;
+                }
+                pressed => {
+                    if pressed.is_none() {
+                        self.context.wait_key = self
+                            .context
+                            .held_keys
+                            .iter()
+                            .position(|&held| held)
+                            .map(|key| key as u8);
+                    }
+                    self.context.decrement_pc();
+                    outcome = StepOutcome::Waiting;
+                }
+            },
+            // Set delay timer
+            Instruction::SetDelay(x) => {
+                self.context.delay = self.context.v[x as usize];
             }
             // Set audio timer
-            (0xF, _, 1, 8) => {
-                let x = nibble_2 as usize;
-                self.context.sound = self.context.v[x];
-            }
-            // Add X to I
-            (0xF, _, 1, 0xE) => {
-                let val = self.context.v[nibble_2 as usize];
-                let (res, overflowed) = self.context.i.overflowing_add(val as u16);
+            Instruction::SetSound(x) => {
+                self.context.sound = self.context.v[x as usize];
+            }
+            // Add X to I, only touching VF with the I overflow quirk
+            Instruction::AddI(x) => {
+                let res = self
+                    .context
+                    .i
+                    .wrapping_add(self.context.v[x as usize] as u16);
                 self.context.i = res;
-                if overflowed {
-                    self.context.v[0x0F] = 1;
+                if self.quirks.i_overflow {
+                    self.context.v[0x0F] = (res > 0x0FFF) as u8;
                 }
             }
-            (0xF, _, 3, 3) => {
-                let vx = self.context.v[nibble_2 as usize];
+            // Set I to font character address
+            Instruction::Font(x) => {
+                let val = (self.context.v[x as usize] as u16) * 5;
+
+                self.context.i = (FONT_OFFSET as u16) + val;
+            }
+            // Set I to big font character address
+            Instruction::BigFont(x) => {
+                let val = ((self.context.v[x as usize] & 0xF) as u16) * 10;
+
+                self.context.i = (BIG_FONT_OFFSET as u16) + val;
+            }
+            // Store the decimal digits of vx at I
+            Instruction::Bcd(x) => {
+                let vx = self.context.v[x as usize];
                 let ones = vx % 10;
                 let tens = ((vx % 100) - ones) / 10;
                 let hundreds = (vx - (tens + ones)) / 100;
-                let i = self.context.i as usize;
+                let range = self.memory_range(pc, full, 3)?;
 
-                self.context.memory[i] = hundreds;
-                self.context.memory[i + 1] = tens;
-                self.context.memory[i + 2] = ones;
+                self.context.memory[range].copy_from_slice(&[hundreds, tens, ones]);
             }
-            // Set I to font character address
-            (0xF, _, 2, 9) => {
-                let x = nibble_2 as usize;
-                let val = (self.context.v[x] as u16) * 5;
-
-                self.context.i = (FONT_OFFSET as u16) + val;
+            // Set audio pitch
+            Instruction::Pitch(x) => {
+                self.context.pitch = self.context.v[x as usize];
             }
             // Store v[0] to v[x] in memory (from I)
-            (0xF, _, 5, 5) => {
-                let x = nibble_2;
+            Instruction::Store(x) => {
+                let x = x as usize;
+                let range = self.memory_range(pc, full, x + 1)?;
+
+                self.context.memory[range].copy_from_slice(&self.context.v[..=x]);
 
-                for i in 0..(x + 1) {
-                    self.context.memory[(self.context.i + i) as usize] = self.context.v[i as usize];
+                if self.quirks.load_store {
+                    self.context.i = self.context.i.wrapping_add(x as u16 + 1);
                 }
             }
             // Store memory from I in v[0] to v[x]
-            (0xF, _, 6, 5) => {
-                let x = nibble_2;
+            Instruction::Load(x) => {
+                let x = x as usize;
+                let range = self.memory_range(pc, full, x + 1)?;
 
-                for i in 0..(x + 1) {
-                    self.context.v[i as usize] = self.context.memory[(self.context.i + i) as usize];
+                self.context.v[..=x].copy_from_slice(&self.context.memory[range]);
+
+                if self.quirks.load_store {
+                    self.context.i = self.context.i.wrapping_add(x as u16 + 1);
                 }
             }
-            // Draw to screen
-            (0xD, _, _, _) => {
-                let x = (self.context.v[nibble_2 as usize] % WIDTH as u8) as usize;
-                let mut y = (self.context.v[nibble_3 as usize] % HEIGHT as u8) as usize;
-                self.context.v[15] = 0;
+            // Store v[0] to v[x] in the user flags
+            Instruction::SaveFlags(x) => {
+                let x = x as usize;
+                self.context.rpl[..=x].copy_from_slice(&self.context.v[..=x]);
+            }
+            // Load v[0] to v[x] from the user flags
+            Instruction::LoadFlags(x) => {
+                let x = x as usize;
+                self.context.v[..=x].copy_from_slice(&self.context.rpl[..=x]);
+            }
+            Instruction::Unknown(_) => return Err(Chip8Error::UnknownOpcode { pc, opcode: full }),
+        }
 
-                let i = self.context.i as usize;
-                let end = (self.context.i + nibble_4) as usize;
+        self.advance_cycles(cycles, outcome);
+        self.debugger.waiting = outcome == StepOutcome::Waiting;
+        Ok(outcome)
+    }
 
-                for byte in &self.context.memory[i..end] {
-                    if y >= HEIGHT {
+    // The register 8XY6 and 8XYE shift, VY unless the shift quirk is set
+    fn shift_operand(&self, x: u8, y: u8) -> u8 {
+        if self.quirks.shift {
+            self.context.v[x as usize]
+        } else {
+            self.context.v[y as usize]
+        }
+    }
+
+    // Memory from I to I + len, or an error when that leaves memory
+    fn memory_range(&self, pc: usize, opcode: u16, len: usize) -> Result<Range<usize>, Chip8Error> {
+        let start = self.context.i as usize;
+        let end = start + len;
+        if end > self.context.memory.len() {
+            return Err(Chip8Error::MemoryOutOfBounds {
+                pc,
+                opcode,
+                address: end - 1,
+            });
+        }
+        Ok(start..end)
+    }
+
+    // Registers from x to y, in descending order when x > y
+    fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+        if x <= y {
+            Box::new(x..=y)
+        } else {
+            Box::new((y..=x).rev())
+        }
+    }
+
+    // Draws an 8xN sprite, or a 16x16 sprite when N is 0, to every selected plane.
+    // Each plane reads its own sprite data, following the previous plane's in memory.
+    fn draw_sprite(
+        &mut self,
+        pc: usize,
+        opcode: u16,
+        x: usize,
+        y: usize,
+        height: usize,
+    ) -> Result<(), Chip8Error> {
+        let width = self.context.frame_buffer.width();
+        let screen_height = self.context.frame_buffer.height();
+
+        let x = self.context.v[x] as usize % width;
+        let y = self.context.v[y] as usize % screen_height;
+        self.context.v[15] = 0;
+
+        let (sprite_width, height) = if height == 0 { (16, 16) } else { (8, height) };
+        let row_bytes = sprite_width / 8;
+        let sprite_len = height * row_bytes;
+
+        let selected = self.context.frame_buffer.planes();
+        let planes = selected.count_ones() as usize;
+        let mut i = self.memory_range(pc, opcode, sprite_len * planes)?.start;
+
+        for plane in [1, 2] {
+            if selected & plane == 0 {
+                continue;
+            }
+
+            let end = i + sprite_len;
+            for (row, bytes) in self.context.memory[i..end].chunks(row_bytes).enumerate() {
+                let mut y_row = y + row;
+                if y_row >= screen_height {
+                    if !self.quirks.wrap {
                         break;
                     }
-                    let bits = (0..8).map(|i| (byte >> i) & 1).rev();
-                    let mut x_row = x;
-                    for bit in bits {
-                        if x_row >= WIDTH {
+                    y_row %= screen_height;
+                }
+                let bits = bytes
+                    .iter()
+                    .flat_map(|byte| (0..8).map(move |i| (byte >> i) & 1).rev());
+                for (col, bit) in bits.enumerate() {
+                    let mut x_row = x + col;
+                    if x_row >= width {
+                        if !self.quirks.wrap {
                             break;
                         }
-                        if bit != 0 {
-                            let current_value = self
-                                .context
-                                .frame_buffer
-                                .get_pixel(x_row, y)
-                                .expect("Invalid position");
-
-                            if current_value {
-                                self.context.v[15] = 1;
-                            }
-
-                            self.context
-                                .frame_buffer
-                                .set_pixel(x_row, y, !current_value);
+                        x_row %= width;
+                    }
+                    if bit != 0 {
+                        let current_value = self
+                            .context
+                            .frame_buffer
+                            .get_pixel(x_row, y_row)
+                            .expect("Invalid position");
+
+                        if current_value & plane != 0 {
+                            self.context.v[15] = 1;
                         }
-                        x_row += 1;
+
+                        self.context
+                            .frame_buffer
+                            .set_pixel(x_row, y_row, current_value ^ plane);
                     }
-                    y += 1;
                 }
             }
-            _ => println!("Unknown operation: {:x}", full),
+            i = end;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::{
+        emulator::{Chip8Emulator, EmulatorMode, ROM_OFFSET},
+        error::StepOutcome,
+        quirks::Quirks,
+        random::Generator,
+    };
+
+    // Runs a single instruction with the given registers set beforehand
+    fn run(opcode: u16, quirks: Quirks, registers: &[(usize, u8)]) -> Chip8Emulator {
+        let mut chip8 = Chip8Emulator::new(EmulatorMode::Run);
+        chip8.quirks = quirks;
+        chip8.load_rom(&opcode.to_be_bytes()).unwrap();
+        for &(register, value) in registers {
+            chip8.context.v[register] = value;
+        }
+        chip8.execute_instruction().unwrap();
+        chip8
+    }
+
+    fn run_default(opcode: u16, registers: &[(usize, u8)]) -> Chip8Emulator {
+        run(opcode, Quirks::default(), registers)
+    }
+
+    fn vf_reset() -> Quirks {
+        Quirks {
+            vf_reset: true,
+            ..Quirks::default()
+        }
+    }
+
+    fn shift() -> Quirks {
+        Quirks {
+            shift: true,
+            ..Quirks::default()
+        }
+    }
+
+    fn i_overflow() -> Quirks {
+        Quirks {
+            i_overflow: true,
+            ..Quirks::default()
         }
+    }
+
+    #[test]
+    fn or_and_xor_leave_vf_without_quirk() {
+        for (opcode, result) in [(0x8011, 0x3F), (0x8012, 0x0C), (0x8013, 0x33)] {
+            let chip8 = run_default(opcode, &[(0, 0x3C), (1, 0x0F), (0xF, 0x55)]);
+            assert_eq!(chip8.context.v[0], result, "{:04X}", opcode);
+            assert_eq!(chip8.context.v[0xF], 0x55, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn or_and_xor_reset_vf_with_quirk() {
+        for opcode in [0x8011, 0x8012, 0x8013] {
+            let chip8 = run(opcode, vf_reset(), &[(0, 0x3C), (1, 0x0F), (0xF, 0x55)]);
+            assert_eq!(chip8.context.v[0xF], 0, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn add_sets_carry() {
+        let chip8 = run_default(0x8014, &[(0, 0xF0), (1, 0x20)]);
+        assert_eq!(chip8.context.v[0], 0x10);
+        assert_eq!(chip8.context.v[0xF], 1);
+    }
+
+    #[test]
+    fn add_clears_carry() {
+        let chip8 = run_default(0x8014, &[(0, 0x10), (1, 0x20), (0xF, 0x55)]);
+        assert_eq!(chip8.context.v[0], 0x30);
+        assert_eq!(chip8.context.v[0xF], 0);
+    }
+
+    #[test]
+    fn add_writes_flag_last() {
+        let chip8 = run_default(0x8F14, &[(0xF, 0x10), (1, 0x20)]);
+        assert_eq!(chip8.context.v[0xF], 0);
+
+        let chip8 = run_default(0x80F4, &[(0, 0xF0), (0xF, 0x20)]);
+        assert_eq!(chip8.context.v[0], 0x10);
+        assert_eq!(chip8.context.v[0xF], 1);
+    }
+
+    #[test]
+    fn sub_sets_not_borrow() {
+        let chip8 = run_default(0x8015, &[(0, 0x30), (1, 0x10)]);
+        assert_eq!(chip8.context.v[0], 0x20);
+        assert_eq!(chip8.context.v[0xF], 1);
+
+        let chip8 = run_default(0x8015, &[(0, 0x10), (1, 0x30)]);
+        assert_eq!(chip8.context.v[0], 0xE0);
+        assert_eq!(chip8.context.v[0xF], 0);
+
+        // Equal values do not borrow
+        let chip8 = run_default(0x8015, &[(0, 0x10), (1, 0x10)]);
+        assert_eq!(chip8.context.v[0], 0);
+        assert_eq!(chip8.context.v[0xF], 1);
+    }
+
+    #[test]
+    fn sub_writes_flag_last() {
+        let chip8 = run_default(0x8F15, &[(0xF, 0x10), (1, 0x30)]);
+        assert_eq!(chip8.context.v[0xF], 0);
+    }
+
+    #[test]
+    fn sub_reverse_sets_not_borrow() {
+        let chip8 = run_default(0x8017, &[(0, 0x10), (1, 0x30)]);
+        assert_eq!(chip8.context.v[0], 0x20);
+        assert_eq!(chip8.context.v[0xF], 1);
+
+        let chip8 = run_default(0x8017, &[(0, 0x30), (1, 0x10)]);
+        assert_eq!(chip8.context.v[0], 0xE0);
+        assert_eq!(chip8.context.v[0xF], 0);
+    }
+
+    #[test]
+    fn sub_reverse_writes_flag_last() {
+        let chip8 = run_default(0x8F17, &[(0xF, 0x30), (1, 0x10)]);
+        assert_eq!(chip8.context.v[0xF], 0);
+    }
+
+    #[test]
+    fn shift_right_uses_vy() {
+        let chip8 = run_default(0x8016, &[(0, 0xFF), (1, 0x05)]);
+        assert_eq!(chip8.context.v[0], 0x02);
+        assert_eq!(chip8.context.v[0xF], 1);
+
+        let chip8 = run_default(0x8016, &[(0, 0xFF), (1, 0x04), (0xF, 0x55)]);
+        assert_eq!(chip8.context.v[0], 0x02);
+        assert_eq!(chip8.context.v[0xF], 0);
+    }
+
+    #[test]
+    fn shift_right_uses_vx_with_quirk() {
+        let chip8 = run(0x8016, shift(), &[(0, 0x81), (1, 0x04)]);
+        assert_eq!(chip8.context.v[0], 0x40);
+        assert_eq!(chip8.context.v[0xF], 1);
+    }
+
+    #[test]
+    fn shift_right_writes_flag_last() {
+        let chip8 = run_default(0x8FF6, &[(0xF, 0x05)]);
+        assert_eq!(chip8.context.v[0xF], 1);
+
+        let chip8 = run_default(0x8FF6, &[(0xF, 0x04)]);
+        assert_eq!(chip8.context.v[0xF], 0);
+    }
+
+    #[test]
+    fn shift_left_uses_vy() {
+        let chip8 = run_default(0x801E, &[(0, 0xFF), (1, 0x81)]);
+        assert_eq!(chip8.context.v[0], 0x02);
+        assert_eq!(chip8.context.v[0xF], 1);
+
+        let chip8 = run_default(0x801E, &[(0, 0xFF), (1, 0x41), (0xF, 0x55)]);
+        assert_eq!(chip8.context.v[0], 0x82);
+        assert_eq!(chip8.context.v[0xF], 0);
+    }
+
+    #[test]
+    fn shift_left_uses_vx_with_quirk() {
+        let chip8 = run(0x801E, shift(), &[(0, 0x81), (1, 0x04)]);
+        assert_eq!(chip8.context.v[0], 0x02);
+        assert_eq!(chip8.context.v[0xF], 1);
+    }
+
+    #[test]
+    fn shift_left_writes_flag_last() {
+        let chip8 = run_default(0x8FFE, &[(0xF, 0x81)]);
+        assert_eq!(chip8.context.v[0xF], 1);
+
+        let chip8 = run_default(0x8FFE, &[(0xF, 0x41)]);
+        assert_eq!(chip8.context.v[0xF], 0);
+    }
+
+    #[test]
+    fn add_i_leaves_vf_without_quirk() {
+        let mut chip8 = Chip8Emulator::new(EmulatorMode::Run);
+        chip8.load_rom(&[0xF0, 0x1E]).unwrap();
+        chip8.context.i = 0x0FFF;
+        chip8.context.v[0] = 0x02;
+        chip8.context.v[0xF] = 0x55;
+        chip8.execute_instruction().unwrap();
+        assert_eq!(chip8.context.i, 0x1001);
+        assert_eq!(chip8.context.v[0xF], 0x55);
+    }
+
+    #[test]
+    fn add_i_sets_vf_on_overflow_with_quirk() {
+        for (i, overflow) in [(0x0FFF, 1), (0x0F00, 0)] {
+            let mut chip8 = Chip8Emulator::new(EmulatorMode::Run);
+            chip8.quirks = i_overflow();
+            chip8.load_rom(&[0xF0, 0x1E]).unwrap();
+            chip8.context.i = i;
+            chip8.context.v[0] = 0x02;
+            chip8.context.v[0xF] = 0x55;
+            chip8.execute_instruction().unwrap();
+            assert_eq!(chip8.context.v[0xF], overflow, "I = {:#05x}", i);
+        }
+    }
+
+    // The same seed gives the same CXNN values, masked with NN, and save states keep the
+    // position in the sequence
+    #[test]
+    fn random_follows_seed() {
+        for generator in Generator::ALL {
+            let rolls = |chip8: &mut Chip8Emulator| -> Vec<u8> {
+                (0..8)
+                    .map(|_| {
+                        chip8.context.pc = ROM_OFFSET;
+                        chip8.execute_instruction().unwrap();
+                        chip8.context.v[0]
+                    })
+                    .collect()
+            };
+            let seeded = || {
+                let mut chip8 = Chip8Emulator::new(EmulatorMode::Run);
+                chip8.load_rom(&[0xC0, 0x3F]).unwrap();
+                chip8.seed_random(generator, 1234);
+                chip8
+            };
+
+            let mut first = seeded();
+            let mut second = seeded();
+            assert_eq!(rolls(&mut first), rolls(&mut second));
+            assert!(first.context.v[0] <= 0x3F);
+
+            let state = first.save_state();
+            let expected = rolls(&mut first);
+            second.seed_random(generator, 99);
+            second.load_state(&state).unwrap();
+            assert_eq!(rolls(&mut second), expected);
+        }
+    }
+
+    // FX0A returns a key only once it has been pressed and released again
+    #[test]
+    fn wait_key_needs_press_and_release() {
+        let mut chip8 = Chip8Emulator::new(EmulatorMode::Run);
+        chip8.load_rom(&[0xF3, 0x0A]).unwrap();
+
+        assert_eq!(chip8.execute_instruction(), Ok(StepOutcome::Waiting));
+        chip8.set_keydown(0x7);
+        assert_eq!(chip8.execute_instruction(), Ok(StepOutcome::Waiting));
+        assert_eq!(chip8.execute_instruction(), Ok(StepOutcome::Waiting));
+        assert_eq!(chip8.context.pc, ROM_OFFSET);
 
-        // Don't store input longer than necessary
-        self.context.input = None;
+        chip8.set_keyup(0x7);
+        assert_eq!(chip8.execute_instruction(), Ok(StepOutcome::Continue));
+        assert_eq!(chip8.context.v[3], 0x7);
+        assert_eq!(chip8.context.pc, ROM_OFFSET + 2);
+        assert_eq!(chip8.context.wait_key, None);
     }
 }
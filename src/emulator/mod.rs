@@ -1,4 +1,19 @@
+pub mod assembler;
 pub mod chip8_context;
+pub mod debugger;
+pub mod decoder;
+pub mod disassembler;
+#[allow(clippy::module_inception)]
 pub mod emulator;
+pub mod error;
 pub mod font;
+pub mod gdb;
+pub mod headless;
 pub mod instructions;
+pub mod movie;
+pub mod quirks;
+pub mod random;
+pub mod rewind;
+pub mod savestate;
+pub mod sound;
+pub mod timing;
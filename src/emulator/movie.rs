@@ -0,0 +1,313 @@
+use std::{error::Error, fmt, fs, io, path::Path};
+
+use sha1::{Digest, Sha1};
+
+use super::{emulator::Chip8Emulator, quirks::Quirks, random::Generator, timing::Timing};
+
+// Movies are text, so runs can be edited by hand for tool-assisted runs:
+//   chip8-movie 1
+//   rom <SHA-1>
+//   rng <generator> <seed>
+//   timing <fixed|vip>
+//   ipf <instructions per frame>
+//   memory <bytes>
+//   quirks <names of the quirks that are set>
+//   frames <frames run>
+//   hash <SHA-1 of the end state>
+// followed by one "<frame> down|up <key>" line per key event, the key in hex. Blank lines
+// and lines starting with # are ignored.
+pub const MOVIE_HEADER: &str = "chip8-movie 1";
+
+// A key pressed or released before the given frame ran
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovieEvent {
+    pub frame: u64,
+    pub key: u8,
+    pub down: bool,
+}
+
+// Everything needed to replay a run from power on: the settings, the random seed and the
+// keypad events
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: [u8; 20],
+    pub generator: Generator,
+    pub seed: u64,
+    pub timing: Timing,
+    pub instructions_per_frame: u32,
+    pub memory_size: usize,
+    pub quirks: Quirks,
+    pub events: Vec<MovieEvent>,
+
+    pub frames: u64,
+    pub final_hash: Option<[u8; 20]>,
+}
+
+#[derive(Debug)]
+pub enum MovieError {
+    Io(io::Error),
+    InvalidFormat { line: usize, reason: String },
+    // The movie was recorded with a different ROM
+    RomMismatch,
+    // Playback ended in a different state than the recording
+    StateMismatch { frame: u64 },
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::Io(error) => write!(f, "{}", error),
+            MovieError::InvalidFormat { line, reason } => {
+                write!(f, "Invalid movie, line {}: {}", line, reason)
+            }
+            MovieError::RomMismatch => write!(f, "Movie was recorded with a different ROM"),
+            MovieError::StateMismatch { frame } => {
+                write!(f, "Playback desynced, state differs at frame {}", frame)
+            }
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+impl From<io::Error> for MovieError {
+    fn from(error: io::Error) -> Self {
+        MovieError::Io(error)
+    }
+}
+
+impl Movie {
+    // Starts a recording of the emulator as it is set up now, before its first frame
+    pub fn new(chip8: &Chip8Emulator, generator: Generator, seed: u64) -> Self {
+        Movie {
+            rom_hash: chip8.rom_hash,
+            generator,
+            seed,
+            timing: chip8.timing,
+            instructions_per_frame: chip8.instructions_per_frame,
+            memory_size: chip8.context.memory.len(),
+            quirks: chip8.quirks,
+            events: Vec::new(),
+            frames: 0,
+            final_hash: None,
+        }
+    }
+
+    // Ends the recording at the emulator's current frame and state
+    pub fn finish(&mut self, chip8: &Chip8Emulator) {
+        self.frames = chip8.frame();
+        self.final_hash = Some(chip8.state_hash());
+    }
+
+    // Sets the emulator up like it was when recording started. The ROM must be loaded.
+    pub fn apply(&self, chip8: &mut Chip8Emulator) -> Result<(), MovieError> {
+        if chip8.rom_hash != self.rom_hash {
+            return Err(MovieError::RomMismatch);
+        }
+        chip8.context.memory.resize(self.memory_size, 0);
+        chip8.quirks = self.quirks;
+        chip8.timing = self.timing;
+        chip8.instructions_per_frame = self.instructions_per_frame;
+        chip8.seed_random(self.generator, self.seed);
+        Ok(())
+    }
+
+    // Checks the emulator ended playback in the recorded state
+    pub fn verify(&self, chip8: &Chip8Emulator) -> Result<(), MovieError> {
+        match self.final_hash {
+            Some(hash) if chip8.frame() == self.frames && chip8.state_hash() == hash => Ok(()),
+            _ => Err(MovieError::StateMismatch {
+                frame: chip8.frame(),
+            }),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = format!("{}\n", MOVIE_HEADER);
+        out += &format!("rom {}\n", encode_hex(&self.rom_hash));
+        out += &format!("rng {} {}\n", self.generator, self.seed);
+        out += &format!("timing {}\n", self.timing);
+        out += &format!("ipf {}\n", self.instructions_per_frame);
+        out += &format!("memory {}\n", self.memory_size);
+        out += &format!("quirks {}\n", self.quirks.enabled().join(" "));
+        if let Some(hash) = self.final_hash {
+            out += &format!("frames {}\n", self.frames);
+            out += &format!("hash {}\n", encode_hex(&hash));
+        }
+        for event in &self.events {
+            let action = if event.down { "down" } else { "up" };
+            out += &format!("{} {} {:x}\n", event.frame, action, event.key);
+        }
+        out
+    }
+
+    pub fn parse(text: &str) -> Result<Self, MovieError> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        match lines.next() {
+            Some((_, MOVIE_HEADER)) => {}
+            other => {
+                return Err(MovieError::InvalidFormat {
+                    line: other.map_or(1, |(line, _)| line),
+                    reason: format!("expected '{}'", MOVIE_HEADER),
+                });
+            }
+        }
+
+        let mut movie = Movie {
+            rom_hash: [0; 20],
+            generator: Generator::default(),
+            seed: 0,
+            timing: Timing::default(),
+            instructions_per_frame: 0,
+            memory_size: 0,
+            quirks: Quirks::default(),
+            events: Vec::new(),
+            frames: 0,
+            final_hash: None,
+        };
+        let mut rom = false;
+        let mut finished = false;
+
+        for (line, text) in lines {
+            let invalid = |reason: &str| MovieError::InvalidFormat {
+                line,
+                reason: reason.to_string(),
+            };
+            let fields: Vec<&str> = text.split_whitespace().collect();
+
+            match fields[..] {
+                ["rom", hash] => {
+                    movie.rom_hash =
+                        decode_hash(hash).ok_or_else(|| invalid("invalid ROM hash"))?;
+                    rom = true;
+                }
+                ["rng", generator, seed] => {
+                    movie.generator = generator.parse().map_err(|e: String| invalid(&e))?;
+                    movie.seed = seed.parse().map_err(|_| invalid("invalid seed"))?;
+                }
+                ["timing", timing] => {
+                    movie.timing = timing.parse().map_err(|e: String| invalid(&e))?;
+                }
+                ["ipf", ipf] => {
+                    movie.instructions_per_frame = ipf
+                        .parse()
+                        .map_err(|_| invalid("invalid instructions per frame"))?;
+                }
+                ["memory", size] => {
+                    movie.memory_size = size.parse().map_err(|_| invalid("invalid memory size"))?;
+                }
+                ["quirks", ref names @ ..] => {
+                    movie.quirks = Quirks::default();
+                    for name in names {
+                        *movie
+                            .quirks
+                            .flag_mut(name)
+                            .ok_or_else(|| invalid(&format!("unknown quirk {}", name)))? = true;
+                    }
+                }
+                ["frames", frames] => {
+                    movie.frames = frames.parse().map_err(|_| invalid("invalid frame count"))?;
+                    finished = true;
+                }
+                ["hash", hash] => {
+                    movie.final_hash =
+                        Some(decode_hash(hash).ok_or_else(|| invalid("invalid state hash"))?);
+                }
+                [frame, action @ ("down" | "up"), key] => {
+                    let frame = frame.parse().map_err(|_| invalid("invalid frame"))?;
+                    let key = u8::from_str_radix(key, 16)
+                        .ok()
+                        .filter(|&key| key <= 0xF)
+                        .ok_or_else(|| invalid("invalid key"))?;
+                    if movie.events.last().is_some_and(|last| last.frame > frame) {
+                        return Err(invalid("events must be in frame order"));
+                    }
+                    movie.events.push(MovieEvent {
+                        frame,
+                        key,
+                        down: action == "down",
+                    });
+                }
+                _ => return Err(invalid(&format!("unexpected '{}'", text))),
+            }
+        }
+
+        // Without the end of the recording a truncated movie would end at frame 0
+        if !rom || movie.memory_size == 0 || !finished || movie.final_hash.is_none() {
+            return Err(MovieError::InvalidFormat {
+                line: text.lines().count(),
+                reason: "missing rom, memory, frames or hash line".to_string(),
+            });
+        }
+        Ok(movie)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), MovieError> {
+        fs::write(path, self.to_text())?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, MovieError> {
+        Movie::parse(&fs::read_to_string(path)?)
+    }
+}
+
+// Replays a movie's key events, one frame at a time as the emulator runs them
+#[derive(Debug)]
+pub struct MoviePlayer {
+    pub movie: Movie,
+    next: usize,
+}
+
+impl MoviePlayer {
+    pub fn new(movie: Movie) -> Self {
+        MoviePlayer { movie, next: 0 }
+    }
+
+    // Presses and releases the keys recorded before the emulator's next frame
+    pub fn press_keys(&mut self, chip8: &mut Chip8Emulator) {
+        while let Some(event) = self.movie.events.get(self.next) {
+            if event.frame > chip8.frame() {
+                break;
+            }
+            if event.down {
+                chip8.set_keydown(event.key);
+            } else {
+                chip8.set_keyup(event.key);
+            }
+            self.next += 1;
+        }
+    }
+
+    // All recorded frames have run
+    pub fn is_finished(&self, chip8: &Chip8Emulator) -> bool {
+        chip8.frame() >= self.movie.frames
+    }
+}
+
+impl Chip8Emulator {
+    // SHA-1 of the save state, compares whole emulator states
+    pub fn state_hash(&self) -> [u8; 20] {
+        Sha1::digest(self.save_state()).into()
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hash(text: &str) -> Option<[u8; 20]> {
+    if text.len() != 40 || !text.is_ascii() {
+        return None;
+    }
+    let mut hash = [0; 20];
+    for (byte, pair) in hash.iter_mut().zip(text.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(hash)
+}
@@ -0,0 +1,168 @@
+use std::{fmt, str::FromStr};
+
+use super::chip8_context::{MEMORY_SIZE, XO_MEMORY_SIZE};
+
+// Behaviour of the instructions that differ between Chip-8 implementations.
+// The default is the behaviour this emulator has always had, which is what most modern ROMs expect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Quirks {
+    // 8XY6/8XYE shift VX in place instead of copying VY first
+    pub shift: bool,
+
+    // FX55/FX65 leave I pointing past the last register stored/loaded
+    pub load_store: bool,
+
+    // BNNN behaves as BXNN and jumps to XNN + VX
+    pub jump: bool,
+
+    // DXYN wraps sprites around the screen edges instead of clipping them
+    pub wrap: bool,
+
+    // 8XY1/8XY2/8XY3 reset VF to 0
+    pub vf_reset: bool,
+
+    // DXYN waits for the next vertical blank before drawing
+    pub display_wait: bool,
+
+    // FX1E sets VF when I goes past 0xFFF, as on the Amiga interpreter
+    pub i_overflow: bool,
+}
+
+impl Quirks {
+    pub const NAMES: [&str; 7] = [
+        "shift",
+        "load_store",
+        "jump",
+        "wrap",
+        "vf_reset",
+        "display_wait",
+        "i_overflow",
+    ];
+
+    // The flag of the quirk with the given name from NAMES
+    pub fn flag_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "shift" => Some(&mut self.shift),
+            "load_store" => Some(&mut self.load_store),
+            "jump" => Some(&mut self.jump),
+            "wrap" => Some(&mut self.wrap),
+            "vf_reset" => Some(&mut self.vf_reset),
+            "display_wait" => Some(&mut self.display_wait),
+            "i_overflow" => Some(&mut self.i_overflow),
+            _ => None,
+        }
+    }
+
+    // Names of the quirks that are set
+    pub fn enabled(&self) -> Vec<&'static str> {
+        let mut quirks = *self;
+        Quirks::NAMES
+            .into_iter()
+            .filter(|name| quirks.flag_mut(name).is_some_and(|flag| *flag))
+            .collect()
+    }
+
+    pub const fn new(platform: Platform) -> Self {
+        match platform {
+            Platform::Chip8 => Quirks {
+                shift: false,
+                load_store: true,
+                jump: false,
+                wrap: false,
+                vf_reset: true,
+                display_wait: true,
+                i_overflow: false,
+            },
+            Platform::Chip48 => Quirks {
+                shift: true,
+                load_store: false,
+                jump: true,
+                wrap: false,
+                vf_reset: false,
+                display_wait: false,
+                i_overflow: false,
+            },
+            Platform::SuperChip => Quirks {
+                shift: true,
+                load_store: false,
+                jump: true,
+                wrap: false,
+                vf_reset: false,
+                display_wait: false,
+                i_overflow: false,
+            },
+            Platform::XoChip => Quirks {
+                shift: false,
+                load_store: true,
+                jump: false,
+                wrap: true,
+                vf_reset: false,
+                display_wait: false,
+                i_overflow: false,
+            },
+        }
+    }
+}
+
+impl From<Platform> for Quirks {
+    fn from(platform: Platform) -> Self {
+        Quirks::new(platform)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Chip8,
+    Chip48,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 4] = [
+        Platform::Chip8,
+        Platform::Chip48,
+        Platform::SuperChip,
+        Platform::XoChip,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::Chip8 => "chip8",
+            Platform::Chip48 => "chip48",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xochip",
+        }
+    }
+
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Platform::XoChip => XO_MEMORY_SIZE,
+            _ => MEMORY_SIZE,
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "chip8" | "chip-8" | "vip" | "cosmac" => Ok(Platform::Chip8),
+            "chip48" | "chip-48" => Ok(Platform::Chip48),
+            "schip" | "superchip" | "super-chip" => Ok(Platform::SuperChip),
+            "xochip" | "xo-chip" | "octo" => Ok(Platform::XoChip),
+            _ => Err(format!(
+                "Unknown platform '{}', expected one of: {}",
+                s,
+                Platform::ALL.map(|p| p.name()).join(", ")
+            )),
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::{fmt, str::FromStr};
+
+// Where CXNN gets its random bytes. A source is deterministic and its whole state fits in a
+// u64, which save states keep, so a run can be reproduced from its seed.
+pub trait RandomSource: fmt::Debug {
+    // The next byte, the memory is there for sources that read from it like the VIP's
+    fn next_byte(&mut self, memory: &[u8]) -> u8;
+
+    fn state(&self) -> u64;
+
+    fn set_state(&mut self, state: u64);
+}
+
+// The built in random sources, selectable by name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Generator {
+    #[default]
+    Xorshift,
+    VipStyle,
+}
+
+impl Generator {
+    pub const ALL: [Generator; 2] = [Generator::Xorshift, Generator::VipStyle];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Generator::Xorshift => "xorshift",
+            Generator::VipStyle => "vip-style",
+        }
+    }
+
+    pub fn source(&self, seed: u64) -> Box<dyn RandomSource> {
+        match self {
+            Generator::Xorshift => Box::new(Xorshift::new(seed)),
+            Generator::VipStyle => Box::new(VipStyleRandom::new(seed)),
+        }
+    }
+}
+
+impl fmt::Display for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Generator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "xorshift" => Ok(Generator::Xorshift),
+            "vip-style" => Ok(Generator::VipStyle),
+            _ => Err(format!(
+                "Unknown random generator '{}', expected one of: {}",
+                s,
+                Generator::ALL.map(|g| g.name()).join(", ")
+            )),
+        }
+    }
+}
+
+// xorshift64*, the seed is mixed with splitmix64 so every seed, 0 included, is usable
+#[derive(Debug, Clone)]
+pub struct Xorshift {
+    state: u64,
+}
+
+impl Xorshift {
+    pub fn new(seed: u64) -> Self {
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        Xorshift { state: z.max(1) }
+    }
+}
+
+impl RandomSource for Xorshift {
+    fn next_byte(&mut self, _memory: &[u8]) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+
+    fn state(&self) -> u64 {
+        self.state
+    }
+
+    fn set_state(&mut self, state: u64) {
+        self.state = state.max(1);
+    }
+}
+
+// An approximation of the COSMAC VIP's CXNN, not the VIP's generator: a pointer that moves
+// up one byte per call reads from the interpreter's page, and the byte is added to the last
+// result. The VIP reads its own interpreter code there, while this emulator keeps its fonts
+// there, so the numbers have the VIP's short, correlated sequences but not its values.
+#[derive(Debug, Clone)]
+pub struct VipStyleRandom {
+    pointer: u8,
+    last: u8,
+}
+
+impl VipStyleRandom {
+    pub fn new(seed: u64) -> Self {
+        VipStyleRandom {
+            pointer: seed as u8,
+            last: (seed >> 8) as u8,
+        }
+    }
+}
+
+impl RandomSource for VipStyleRandom {
+    fn next_byte(&mut self, memory: &[u8]) -> u8 {
+        self.pointer = self.pointer.wrapping_add(1);
+        let byte = memory.get(self.pointer as usize).copied().unwrap_or(0);
+        self.last = self.last.wrapping_add(byte).rotate_right(1);
+        self.last
+    }
+
+    fn state(&self) -> u64 {
+        u64::from_le_bytes([self.pointer, self.last, 0, 0, 0, 0, 0, 0])
+    }
+
+    fn set_state(&mut self, state: u64) {
+        let bytes = state.to_le_bytes();
+        self.pointer = bytes[0];
+        self.last = bytes[1];
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::VecDeque;
+
+use super::emulator::Chip8Emulator;
+
+pub const FRAMES_PER_SECOND: usize = 60;
+
+// History of per-frame save states for rewinding. Only the newest state is kept whole,
+// older frames are stored as the difference to the frame after them, which is small
+// since most of memory and the screen stay the same from one frame to the next.
+#[derive(Debug)]
+pub struct Rewind {
+    capacity: usize,
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl Rewind {
+    pub fn new(seconds: usize) -> Self {
+        Rewind {
+            capacity: seconds.saturating_mul(FRAMES_PER_SECOND),
+            latest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    // Number of frames that can currently be rewound
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+    }
+
+    // Bytes used by the history, for choosing a sensible length
+    pub fn memory_usage(&self) -> usize {
+        self.latest.as_ref().map_or(0, Vec::len) + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+
+    // Records the state at the end of a frame
+    pub fn push(&mut self, chip8: &Chip8Emulator) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let state = chip8.save_state();
+        if let Some(latest) = self.latest.take() {
+            // The state size only changes with the platform, older history is useless then
+            if latest.len() == state.len() {
+                self.deltas.push_back(encode_delta(&state, &latest));
+                if self.deltas.len() > self.capacity {
+                    self.deltas.pop_front();
+                }
+            } else {
+                self.deltas.clear();
+            }
+        }
+        self.latest = Some(state);
+    }
+
+    // Steps the emulator back one frame, false once the history is exhausted.
+    // Keys held right now stay held rather than being restored from the past.
+    pub fn step_back(&mut self, chip8: &mut Chip8Emulator) -> bool {
+        let (Some(latest), Some(delta)) = (self.latest.as_mut(), self.deltas.pop_back()) else {
+            return false;
+        };
+        apply_delta(latest, &delta);
+
+        let held_keys = chip8.context.held_keys;
+        let restored = chip8.load_state(latest).is_ok();
+        chip8.context.held_keys = held_keys;
+        restored
+    }
+}
+
+// Deltas are the XOR of two states, stored as runs of (unchanged u16, changed u8, bytes)
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+
+    while pos < from.len() {
+        let unchanged = from[pos..]
+            .iter()
+            .zip(&to[pos..])
+            .take(u16::MAX as usize)
+            .take_while(|(a, b)| a == b)
+            .count();
+        pos += unchanged;
+
+        let changed = from[pos..]
+            .iter()
+            .zip(&to[pos..])
+            .take(u8::MAX as usize)
+            .take_while(|(a, b)| a != b)
+            .count();
+
+        // Trailing unchanged bytes need no run, even past the longest gap a run can skip
+        if changed == 0 && from[pos..] == to[pos..] {
+            break;
+        }
+
+        out.extend_from_slice(&(unchanged as u16).to_le_bytes());
+        out.push(changed as u8);
+        out.extend(
+            from[pos..pos + changed]
+                .iter()
+                .zip(&to[pos..])
+                .map(|(a, b)| a ^ b),
+        );
+        pos += changed;
+    }
+
+    out
+}
+
+fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let mut pos = 0;
+    let mut runs = delta;
+
+    while let [low, high, changed, rest @ ..] = runs {
+        pos += u16::from_le_bytes([*low, *high]) as usize;
+        let changed = *changed as usize;
+        for (byte, diff) in state[pos..pos + changed].iter_mut().zip(&rest[..changed]) {
+            *byte ^= diff;
+        }
+        pos += changed;
+        runs = &rest[changed..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{
+        emulator::EmulatorMode,
+        quirks::Platform,
+        random::{RandomSource, Xorshift},
+    };
+
+    // An XO-CHIP machine, whose 64K of memory has unchanged stretches longer than a run
+    fn emulator() -> Chip8Emulator {
+        let mut chip8 = Chip8Emulator::new(EmulatorMode::Run);
+        chip8.set_platform(Platform::XoChip);
+        chip8
+    }
+
+    // Changes a few random bytes of memory, registers and the screen, as a frame would
+    fn scramble(chip8: &mut Chip8Emulator, random: &mut Xorshift) {
+        let mut next = || random.next_byte(&[]) as usize;
+        for _ in 0..next() % 16 {
+            let address = (next() << 8 | next()) % chip8.context.memory.len();
+            chip8.context.memory[address] = next() as u8;
+        }
+        // Sometimes a long changed run, like a memory copy
+        if next() % 4 == 0 {
+            let start = (next() << 8) % (chip8.context.memory.len() - 600);
+            for byte in &mut chip8.context.memory[start..start + 600] {
+                *byte = !*byte;
+            }
+        }
+        chip8.context.v[next() % 16] = next() as u8;
+        chip8.context.i = (next() << 8 | next()) as u16;
+        chip8.context.delay = next() as u8;
+        let (x, y) = (next() % 64, next() % 32);
+        chip8.context.frame_buffer.set_pixel(x, y, next() as u8 & 3);
+    }
+
+    // Runs frames with random changes, returning the state at the end of each
+    fn record(chip8: &mut Chip8Emulator, rewind: &mut Rewind, frames: usize) -> Vec<Vec<u8>> {
+        let mut random = Xorshift::new(frames as u64);
+        (0..frames)
+            .map(|_| {
+                scramble(chip8, &mut random);
+                rewind.push(chip8);
+                chip8.save_state()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_frame_is_restored() {
+        let mut chip8 = emulator();
+        let mut rewind = Rewind::new(1);
+        let states = record(&mut chip8, &mut rewind, 40);
+        assert_eq!(rewind.len(), 39);
+
+        // The first step lands on the frame before the current one
+        for state in states.iter().rev().skip(1) {
+            assert!(rewind.step_back(&mut chip8));
+            assert!(chip8.save_state() == *state);
+        }
+        assert!(!rewind.step_back(&mut chip8));
+        assert!(rewind.is_empty());
+    }
+
+    #[test]
+    fn recording_continues_after_rewinding() {
+        let mut chip8 = emulator();
+        let mut rewind = Rewind::new(1);
+        let states = record(&mut chip8, &mut rewind, 10);
+        for _ in 0..4 {
+            rewind.step_back(&mut chip8);
+        }
+        let mut branch = record(&mut chip8, &mut rewind, 5);
+        assert_eq!(rewind.len(), 10);
+
+        branch.pop();
+        for state in branch.iter().rev().chain(states[..6].iter().rev()) {
+            assert!(rewind.step_back(&mut chip8));
+            assert!(chip8.save_state() == *state);
+        }
+        assert!(!rewind.step_back(&mut chip8));
+    }
+
+    #[test]
+    fn oldest_frames_are_dropped_at_capacity() {
+        let mut chip8 = emulator();
+        let mut rewind = Rewind::new(1);
+        let states = record(&mut chip8, &mut rewind, FRAMES_PER_SECOND + 25);
+        assert_eq!(rewind.len(), FRAMES_PER_SECOND);
+
+        while rewind.step_back(&mut chip8) {}
+        assert!(chip8.save_state() == states[24]);
+    }
+
+    #[test]
+    fn held_keys_are_kept() {
+        let mut chip8 = emulator();
+        let mut rewind = Rewind::new(1);
+        record(&mut chip8, &mut rewind, 3);
+        chip8.context.held_keys[5] = true;
+
+        assert!(rewind.step_back(&mut chip8));
+        assert!(chip8.context.held_keys[5]);
+    }
+
+    #[test]
+    fn deltas_cover_long_runs() {
+        let old = vec![0; 70_000];
+        let mut new = old.clone();
+        new[0] = 1;
+        new[1000..1300].fill(2);
+        new[69_999] = 3;
+
+        let mut state = new.clone();
+        apply_delta(&mut state, &encode_delta(&new, &old));
+        assert!(state == old);
+        assert!(encode_delta(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn no_history_without_capacity() {
+        let mut chip8 = emulator();
+        let mut rewind = Rewind::new(0);
+        record(&mut chip8, &mut rewind, 3);
+        assert_eq!(rewind.memory_usage(), 0);
+        assert!(!rewind.step_back(&mut chip8));
+    }
+
+    #[test]
+    fn platform_change_drops_history() {
+        let mut chip8 = emulator();
+        let mut rewind = Rewind::new(1);
+        record(&mut chip8, &mut rewind, 3);
+        chip8.set_platform(Platform::Chip8);
+        rewind.push(&chip8);
+        assert!(rewind.is_empty());
+    }
+}
@@ -0,0 +1,235 @@
+use std::{error::Error, fmt, fs, io, path::Path};
+
+use super::{
+    chip8_context::{HIRES_HEIGHT, HIRES_WIDTH},
+    emulator::Chip8Emulator,
+};
+
+// Save state layout, all numbers little-endian:
+//   magic "C8SS", version u16, SHA-1 of the ROM (20 bytes)
+//   memory size u32, memory
+//   V (16), stack (16 x u16), SP u8, I u16, PC u32, delay u8, sound u8, vblank u8
+//   held keys (16), key FX0A waits to be released u8 (0xFF for none)
+//   RPL flags (16), audio pattern (16), pitch u8
+//   hires u8, selected planes u8, framebuffer (128 x 64)
+//   random source state u64
+//   frame u64, instructions this frame u32, VIP machine cycles u64
+pub const SAVE_STATE_MAGIC: &[u8; 4] = b"C8SS";
+pub const SAVE_STATE_VERSION: u16 = 3;
+
+#[derive(Debug)]
+pub enum SaveStateError {
+    Io(io::Error),
+    // Not a save state, or truncated
+    InvalidFormat(String),
+    UnsupportedVersion(u16),
+    // The state was saved while running a different ROM
+    RomMismatch,
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::Io(error) => write!(f, "{}", error),
+            SaveStateError::InvalidFormat(reason) => write!(f, "Invalid save state: {}", reason),
+            SaveStateError::UnsupportedVersion(version) => write!(
+                f,
+                "Save state version {} is not supported, expected {}",
+                version, SAVE_STATE_VERSION
+            ),
+            SaveStateError::RomMismatch => write!(f, "Save state belongs to a different ROM"),
+        }
+    }
+}
+
+impl Error for SaveStateError {}
+
+impl From<io::Error> for SaveStateError {
+    fn from(error: io::Error) -> Self {
+        SaveStateError::Io(error)
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], SaveStateError> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| SaveStateError::InvalidFormat("unexpected end of data".to_string()))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SaveStateError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, SaveStateError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, SaveStateError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SaveStateError> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+}
+
+impl Chip8Emulator {
+    pub fn save_state(&self) -> Vec<u8> {
+        let context = &self.context;
+        let mut out = Vec::with_capacity(context.memory.len() + HIRES_WIDTH * HIRES_HEIGHT + 256);
+
+        out.extend_from_slice(SAVE_STATE_MAGIC);
+        out.extend_from_slice(&SAVE_STATE_VERSION.to_le_bytes());
+        out.extend_from_slice(&self.rom_hash);
+
+        out.extend_from_slice(&(context.memory.len() as u32).to_le_bytes());
+        out.extend_from_slice(&context.memory);
+
+        out.extend_from_slice(&context.v);
+        for entry in context.stack {
+            out.extend_from_slice(&entry.to_le_bytes());
+        }
+        out.push(context.sp as u8);
+        out.extend_from_slice(&context.i.to_le_bytes());
+        out.extend_from_slice(&(context.pc as u32).to_le_bytes());
+        out.push(context.delay);
+        out.push(context.sound);
+        out.push(context.vblank as u8);
+
+        out.extend(context.held_keys.map(|held| held as u8));
+        out.push(context.wait_key.unwrap_or(0xFF));
+
+        out.extend_from_slice(&context.rpl);
+        out.extend_from_slice(&context.audio_pattern);
+        out.push(context.pitch);
+
+        let frame_buffer = &context.frame_buffer;
+        out.push(frame_buffer.hires as u8);
+        out.push(frame_buffer.planes);
+        out.extend_from_slice(&frame_buffer.buffer);
+
+        out.extend_from_slice(&self.random.state().to_le_bytes());
+
+        out.extend_from_slice(&self.frame.to_le_bytes());
+        out.extend_from_slice(&self.frame_instructions.to_le_bytes());
+        out.extend_from_slice(&self.machine_cycles.to_le_bytes());
+
+        out
+    }
+
+    // Restores a state saved with save_state. Nothing is changed if the state is invalid.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let mut reader = Reader { data, pos: 0 };
+
+        if reader.bytes(4)? != SAVE_STATE_MAGIC {
+            return Err(SaveStateError::InvalidFormat("missing header".to_string()));
+        }
+        let version = reader.u16()?;
+        if version != SAVE_STATE_VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+        if reader.array::<20>()? != self.rom_hash {
+            return Err(SaveStateError::RomMismatch);
+        }
+
+        let memory_size = reader.u32()? as usize;
+        if memory_size != self.context.memory.len() {
+            return Err(SaveStateError::InvalidFormat(format!(
+                "state has {} bytes of memory, emulator has {}",
+                memory_size,
+                self.context.memory.len()
+            )));
+        }
+        let memory = reader.bytes(memory_size)?;
+
+        let v = reader.array::<16>()?;
+        let mut stack = [0; 16];
+        for entry in stack.iter_mut() {
+            *entry = reader.u16()?;
+        }
+        let sp = reader.u8()? as usize;
+        let i = reader.u16()?;
+        let pc = reader.u32()? as usize;
+        let delay = reader.u8()?;
+        let sound = reader.u8()?;
+        let vblank = reader.u8()? != 0;
+
+        let held_keys = reader.array::<16>()?.map(|held| held != 0);
+        let wait_key = match reader.u8()? {
+            0xFF => None,
+            key => Some(key & 0xF),
+        };
+
+        let rpl = reader.array::<16>()?;
+        let audio_pattern = reader.array::<16>()?;
+        let pitch = reader.u8()?;
+
+        let hires = reader.u8()? != 0;
+        let planes = reader.u8()?;
+        let buffer = reader.array::<{ HIRES_WIDTH * HIRES_HEIGHT }>()?;
+
+        let random = reader.u64()?;
+        let frame = reader.u64()?;
+        let frame_instructions = reader.u32()?;
+        let machine_cycles = reader.u64()?;
+
+        if sp > stack.len() {
+            return Err(SaveStateError::InvalidFormat(format!(
+                "stack pointer {}",
+                sp
+            )));
+        }
+
+        let context = &mut self.context;
+        context.memory.copy_from_slice(memory);
+        context.v = v;
+        context.stack = stack;
+        context.sp = sp;
+        context.i = i;
+        context.pc = pc;
+        context.delay = delay;
+        context.sound = sound;
+        context.vblank = vblank;
+        context.held_keys = held_keys;
+        context.wait_key = wait_key;
+        context.rpl = rpl;
+        context.audio_pattern = audio_pattern;
+        context.pitch = pitch;
+
+        let frame_buffer = &mut context.frame_buffer;
+        frame_buffer.hires = hires;
+        frame_buffer.planes = planes & 0b11;
+        frame_buffer.buffer = buffer;
+        frame_buffer.mark_dirty();
+
+        self.random.set_state(random);
+        self.frame = frame;
+        self.frame_instructions = frame_instructions;
+        self.machine_cycles = machine_cycles;
+
+        Ok(())
+    }
+
+    pub fn save_state_to_file(&self, path: &Path) -> Result<(), SaveStateError> {
+        fs::write(path, self.save_state())?;
+        Ok(())
+    }
+
+    pub fn load_state_from_file(&mut self, path: &Path) -> Result<(), SaveStateError> {
+        let data = fs::read(path)?;
+        self.load_state(&data)
+    }
+}
@@ -0,0 +1,20 @@
+// What the frontend should play, see Chip8Emulator::sound
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sound {
+    pub playing: bool,
+    pub pattern: [u8; 16],
+    pub pitch: u8,
+}
+
+impl Sound {
+    // The 128 bit pattern is played back at 4000 * 2^((pitch - 64) / 48) bits per second
+    pub fn rate(&self) -> f64 {
+        4000.0 * 2f64.powf((self.pitch as f64 - 64.0) / 48.0)
+    }
+
+    // Whether the given bit of the pattern is set, wrapping around the end
+    pub fn bit(&self, position: usize) -> bool {
+        let bit = position % 128;
+        (self.pattern[bit / 8] >> (7 - bit % 8)) & 1 == 1
+    }
+}
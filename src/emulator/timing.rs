@@ -0,0 +1,150 @@
+use std::{fmt, str::FromStr};
+
+use super::{decoder::Instruction, emulator::Chip8Emulator, error::StepOutcome};
+
+// The VIP's 1802 runs at 1.76064 MHz and takes 8 clock cycles per machine cycle
+pub const VIP_CYCLES_PER_SECOND: u64 = 1_760_640 / 8;
+
+// Machine cycles between two display interrupts, the 60 Hz frame the timers count down in
+pub const VIP_CYCLES_PER_FRAME: u64 = VIP_CYCLES_PER_SECOND / 60;
+
+// Cycles each frame loses to the video DMA, 128 lines of 8 bytes, and the interrupt routine
+// that decrements the timers
+const VIP_INTERRUPT_CYCLES: u64 = 1024 + 46;
+
+// Fetching and decoding, paid by every instruction before it is dispatched
+const VIP_FETCH_CYCLES: u64 = 40;
+
+// How the time an instruction takes is decided
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Timing {
+    // Every frame runs the same number of instructions
+    #[default]
+    Fixed,
+    // Each instruction costs the machine cycles it took in the COSMAC VIP interpreter, and
+    // the timers tick every VIP_CYCLES_PER_FRAME emulated cycles
+    CosmacVip,
+}
+
+impl Timing {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Timing::Fixed => "fixed",
+            Timing::CosmacVip => "vip",
+        }
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Timing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fixed" => Ok(Timing::Fixed),
+            "vip" | "cosmac" => Ok(Timing::CosmacVip),
+            _ => Err(format!("Unknown timing '{}', expected fixed or vip", s)),
+        }
+    }
+}
+
+impl Chip8Emulator {
+    // Machine cycles emulated so far, only counted with VIP timing
+    pub fn machine_cycles(&self) -> u64 {
+        self.machine_cycles
+    }
+
+    // Display interrupts seen so far with VIP timing
+    pub fn vip_frame(&self) -> u64 {
+        self.machine_cycles / VIP_CYCLES_PER_FRAME
+    }
+
+    // Machine cycles the instruction about to run costs on a COSMAC VIP, 0 with fixed timing.
+    // The costs follow the published cycle counts of the original interpreter. Loops that
+    // depend on the data, like DXYN shifting sprite bytes into place, are charged per
+    // iteration. SUPER-CHIP and XO-CHIP instructions never ran on the VIP and only pay the
+    // fetch.
+    pub(crate) fn instruction_cycles(&self, instruction: Instruction) -> u64 {
+        if self.timing == Timing::Fixed {
+            return 0;
+        }
+
+        let v = &self.context.v;
+        let skip = |taken: bool| if taken { 14 } else { 10 };
+        let cycles = match instruction {
+            Instruction::Cls => 24,
+            Instruction::Ret => 10,
+            Instruction::Jump(_) => 12,
+            Instruction::Call(_) => 26,
+            Instruction::SkipEqImm(x, nn) => skip(v[x as usize] == nn),
+            Instruction::SkipNeImm(x, nn) => skip(v[x as usize] != nn),
+            Instruction::SkipEq(x, y) => 4 + skip(v[x as usize] == v[y as usize]),
+            Instruction::SkipNe(x, y) => 4 + skip(v[x as usize] != v[y as usize]),
+            Instruction::LoadImm(..) => 6,
+            Instruction::AddImm(..) => 10,
+            Instruction::Move(..) => 12,
+            Instruction::Or(..)
+            | Instruction::And(..)
+            | Instruction::Xor(..)
+            | Instruction::Add(..)
+            | Instruction::Sub(..)
+            | Instruction::ShiftRight(..)
+            | Instruction::SubReverse(..)
+            | Instruction::ShiftLeft(..) => 44,
+            Instruction::LoadI(_) => 12,
+            Instruction::JumpOffset(_) => 22,
+            Instruction::Random(..) => 36,
+            // Each sprite row is shifted one bit at a time to the pixel position in its byte
+            Instruction::Draw(x, _, n) => {
+                let shift = v[x as usize] as u64 % 8;
+                26 + n as u64 * (46 + 8 * shift)
+            }
+            Instruction::SkipKey(x) => {
+                4 + skip(self.context.held_keys[v[x as usize] as usize & 0xF])
+            }
+            Instruction::SkipNotKey(x) => {
+                4 + skip(!self.context.held_keys[v[x as usize] as usize & 0xF])
+            }
+            Instruction::LoadDelay(_) | Instruction::SetDelay(_) | Instruction::SetSound(_) => 10,
+            Instruction::WaitKey(_) => 10,
+            Instruction::AddI(_) | Instruction::Font(_) => 16,
+            // Digits are found by repeated subtraction
+            Instruction::Bcd(x) => {
+                let vx = v[x as usize];
+                84 + 16 * (vx / 100 + vx / 10 % 10 + vx % 10) as u64
+            }
+            Instruction::Store(x) | Instruction::Load(x) => 14 + 14 * (x as u64 + 1),
+            _ => 0,
+        };
+
+        VIP_FETCH_CYCLES + cycles
+    }
+
+    // Advances the emulated clock past an instruction, ticking the timers at each display
+    // interrupt. A waiting instruction sleeps until the next interrupt, like the VIP does
+    // while it waits for vertical blank or a key.
+    pub(crate) fn advance_cycles(&mut self, cycles: u64, outcome: StepOutcome) {
+        if self.timing == Timing::Fixed {
+            return;
+        }
+
+        let cycles = match outcome {
+            StepOutcome::Waiting => {
+                VIP_CYCLES_PER_FRAME - self.machine_cycles % VIP_CYCLES_PER_FRAME
+            }
+            _ => cycles,
+        };
+
+        let frame = self.vip_frame();
+        self.machine_cycles += cycles;
+        for _ in frame..self.vip_frame() {
+            self.context.tick_timers();
+            self.machine_cycles += VIP_INTERRUPT_CYCLES;
+        }
+    }
+}
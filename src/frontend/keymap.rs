@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+// The usual layout, the left four columns of the keyboard in the shape of the COSMAC VIP
+// keypad:
+//   1 2 3 4      1 2 3 C
+//   Q W E R  ->  4 5 6 D
+//   A S D F      7 8 9 E
+//   Z X C V      A 0 B F
+// On game controllers the D-pad and left stick act as W A S D, and A and B as E and Q.
+const DEFAULT_KEYS: [(&str, u8); 26] = [
+    ("1", 0x1),
+    ("2", 0x2),
+    ("3", 0x3),
+    ("4", 0xC),
+    ("Q", 0x4),
+    ("W", 0x5),
+    ("E", 0x6),
+    ("R", 0xD),
+    ("A", 0x7),
+    ("S", 0x8),
+    ("D", 0x9),
+    ("F", 0xE),
+    ("Z", 0xA),
+    ("X", 0x0),
+    ("C", 0xB),
+    ("V", 0xF),
+    ("pad:dpup", 0x5),
+    ("pad:dpleft", 0x7),
+    ("pad:dpdown", 0x8),
+    ("pad:dpright", 0x9),
+    ("pad:lefty-", 0x5),
+    ("pad:leftx-", 0x7),
+    ("pad:lefty+", 0x8),
+    ("pad:leftx+", 0x9),
+    ("pad:a", 0x6),
+    ("pad:b", 0x4),
+];
+
+// Maps host keys to CHIP-8 keypad keys. Host keys are named like SDL names them, such as
+// "Q", "1" or "Left", and compared without case. Game controller buttons are "pad:" and
+// the SDL button name, such as "pad:a" or "pad:dpup", and stick directions "pad:" and the
+// axis with a sign, such as "pad:leftx-". Keys that are not mapped are ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    keys: HashMap<String, u8>,
+}
+
+impl Keymap {
+    // A keymap without any keys
+    pub fn empty() -> Self {
+        Keymap {
+            keys: HashMap::new(),
+        }
+    }
+
+    // The keypad key a host key is mapped to
+    pub fn key(&self, name: &str) -> Option<u8> {
+        self.keys.get(&name.to_ascii_lowercase()).copied()
+    }
+
+    // Maps a host key to a keypad key, replacing what it was mapped to before
+    pub fn bind(&mut self, name: &str, key: u8) {
+        self.keys.insert(name.to_ascii_lowercase(), key & 0xF);
+    }
+
+    // Maps a keypad key to the given host keys only, dropping its other host keys
+    pub fn rebind(&mut self, key: u8, names: &[String]) {
+        self.keys.retain(|_, bound| *bound != key & 0xF);
+        for name in names {
+            self.bind(name, key);
+        }
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keymap = Keymap::empty();
+        for (name, key) in DEFAULT_KEYS {
+            keymap.bind(name, key);
+        }
+        keymap
+    }
+}
@@ -0,0 +1,39 @@
+use crate::emulator::{chip8_context::FrameBuffer, sound::Sound};
+
+pub mod keymap;
+pub mod palette;
+#[cfg(feature = "sdl")]
+pub mod sdl;
+
+// Events a frontend reports back to the emulator loop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Quit,
+    // Run a single instruction while in step mode
+    Step,
+    // CHIP-8 keypad index 0x0 to 0xF
+    KeyDown(u8),
+    KeyUp(u8),
+    // Save state hotkeys, acting on the selected slot
+    SaveState,
+    LoadState,
+    NextSlot,
+    // Run backwards while the rewind key is held
+    StartRewind,
+    StopRewind,
+}
+
+pub trait Display {
+    fn draw(&mut self, frame_buffer: &FrameBuffer) -> Result<(), String>;
+
+    // Short message for the user, such as a fault that halted the emulator
+    fn show_status(&mut self, status: &str);
+}
+
+pub trait Audio {
+    fn update(&mut self, sound: &Sound);
+}
+
+pub trait Frontend: Display + Audio {
+    fn poll_events(&mut self) -> Vec<Event>;
+}
@@ -0,0 +1,108 @@
+use std::str::FromStr;
+
+// The colours pixels are drawn in, as 0xRRGGBB: the background, plane 1, plane 2 and both
+// planes. CHIP-8 and SUPER-CHIP programs only draw to plane 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [u32; 4],
+}
+
+impl Palette {
+    pub const NAMES: [&'static str; 6] = ["mono", "inverted", "amber", "green", "lcd", "octo"];
+
+    // White on black, with greys for the XO-CHIP planes
+    pub const MONO: Palette = Palette {
+        colors: [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555],
+    };
+
+    pub const INVERTED: Palette = Palette {
+        colors: [0xFFFFFF, 0x000000, 0x555555, 0xAAAAAA],
+    };
+
+    // Monochrome CRT monitors, the second plane dimmer and both planes brighter
+    pub const AMBER: Palette = Palette {
+        colors: [0x140C00, 0xFFB000, 0x8A5F00, 0xFFDC8C],
+    };
+
+    pub const GREEN: Palette = Palette {
+        colors: [0x001400, 0x33FF33, 0x14991F, 0xB4FFB4],
+    };
+
+    // The four greens of early handheld LCDs, dark pixels on a light screen
+    pub const LCD: Palette = Palette {
+        colors: [0x9BBC0F, 0x0F380F, 0x6A8F1F, 0x306230],
+    };
+
+    // Octo's defaults: background, fill, fill2 and blend
+    pub const OCTO: Palette = Palette {
+        colors: [0x996600, 0xFFCC00, 0xFF6600, 0x662200],
+    };
+
+    pub fn named(name: &str) -> Option<Palette> {
+        match name.to_ascii_lowercase().as_str() {
+            "mono" => Some(Palette::MONO),
+            "inverted" => Some(Palette::INVERTED),
+            "amber" => Some(Palette::AMBER),
+            "green" | "phosphor" => Some(Palette::GREEN),
+            "lcd" => Some(Palette::LCD),
+            "octo" => Some(Palette::OCTO),
+            _ => None,
+        }
+    }
+
+    // The palette with its first colours replaced, in the order of the planes
+    pub fn with_colors(&self, colors: &[&str]) -> Result<Palette, String> {
+        if colors.len() > 4 {
+            return Err(format!(
+                "{} colours given, at most 4 are used",
+                colors.len()
+            ));
+        }
+        let mut palette = *self;
+        for (color, text) in palette.colors.iter_mut().zip(colors) {
+            *color = parse_color(text)?;
+        }
+        Ok(palette)
+    }
+
+    // The colour of a pixel value, by its planes
+    pub fn rgb(&self, pixel: u8) -> (u8, u8, u8) {
+        let color = self.colors[pixel as usize & 3];
+        ((color >> 16) as u8, (color >> 8) as u8, color as u8)
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::MONO
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    // A theme by name, or up to four colours separated by commas
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(palette) = Palette::named(s) {
+            return Ok(palette);
+        }
+        if s.contains(',') || parse_color(s).is_ok() {
+            let colors: Vec<&str> = s.split(',').map(str::trim).collect();
+            return Palette::default().with_colors(&colors);
+        }
+        Err(format!(
+            "Unknown palette '{}', expected one of {} or colours like 000000,FFFFFF",
+            s,
+            Palette::NAMES.join(", ")
+        ))
+    }
+}
+
+// A colour written as RRGGBB in hex, with or without a leading #
+pub fn parse_color(text: &str) -> Result<u32, String> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid colour '{}', expected RRGGBB", text));
+    }
+    u32::from_str_radix(hex, 16).map_err(|e| e.to_string())
+}
@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+
+use sdl2::{
+    EventPump, GameControllerSubsystem,
+    audio::{AudioQueue, AudioSpecDesired},
+    controller::{Axis, GameController},
+    event::Event as SdlEvent,
+    keyboard::Keycode,
+    pixels::Color,
+    rect::Rect,
+    render::Canvas,
+    video::Window,
+};
+
+use crate::emulator::{
+    chip8_context::{FrameBuffer, HEIGHT, TIMER_SPEED, WIDTH},
+    sound::Sound,
+};
+
+use super::{Audio, Display, Event, Frontend, keymap::Keymap, palette::Palette};
+
+// Window pixels per lores pixel
+pub const DEFAULT_SCALE: u32 = 10;
+
+// Stick movements closer to the centre than this are ignored, about a quarter of the range
+pub const DEFAULT_DEADZONE: i16 = 8000;
+
+pub struct SdlFrontend {
+    canvas: Canvas<Window>,
+    event_pump: EventPump,
+    audio_queue: AudioQueue<i16>,
+
+    // Position in the audio pattern, in bits
+    audio_position: f64,
+
+    // Window pixels per lores pixel, the picture is scaled to fit when fullscreen
+    scale: u32,
+    // The canvas size drawn on, SDL scales it to the window
+    logical_size: (u32, u32),
+    pub palette: Palette,
+    // Window pixels left blank between screen pixels, like the grid of an LCD
+    pub grid: u32,
+    pub muted: bool,
+    // A fraction of the full volume
+    pub volume: f32,
+
+    pub keymap: Keymap,
+
+    // Game controllers are opened as they are plugged in, SDL reports the ones already
+    // connected at startup the same way
+    controller_subsystem: GameControllerSubsystem,
+    controllers: HashMap<u32, GameController>,
+    // The direction each stick is pushed in along an axis, -1, 0 or 1
+    axes: HashMap<(u32, Axis), i8>,
+    pub deadzone: i16,
+}
+
+impl SdlFrontend {
+    pub fn new(scale: u32, fullscreen: bool) -> Result<Self, String> {
+        let sdl_context = sdl2::init()?;
+        let audio_subsystem = sdl_context.audio()?;
+
+        let audio_spec = AudioSpecDesired {
+            freq: Some(44100),
+            channels: Some(2),
+            samples: None,
+        };
+
+        let audio_queue: AudioQueue<i16> = audio_subsystem.open_queue(None, &audio_spec)?;
+
+        audio_queue.resume();
+
+        let video_subsystem = sdl_context.video()?;
+        let (width, height) = (WIDTH as u32 * scale, HEIGHT as u32 * scale);
+        let mut window = video_subsystem.window("CHIP-8 Emulator", width, height);
+        window.position_centered();
+        if fullscreen {
+            window.fullscreen_desktop();
+        }
+        let window = window.build().map_err(|e| e.to_string())?;
+
+        let mut canvas = window
+            .into_canvas()
+            .present_vsync()
+            .build()
+            .map_err(|e| e.to_string())?;
+        canvas
+            .set_logical_size(width, height)
+            .map_err(|e| e.to_string())?;
+
+        let controller_subsystem = sdl_context.game_controller()?;
+        let event_pump = sdl_context.event_pump()?;
+
+        Ok(SdlFrontend {
+            canvas,
+            event_pump,
+            audio_queue,
+            audio_position: 0.0,
+            scale,
+            logical_size: (width, height),
+            palette: Palette::default(),
+            grid: 0,
+            muted: false,
+            volume: 1.0,
+            keymap: Keymap::default(),
+            controller_subsystem,
+            controllers: HashMap::new(),
+            axes: HashMap::new(),
+            deadzone: DEFAULT_DEADZONE,
+        })
+    }
+
+    fn controller_added(&mut self, index: u32) {
+        match self.controller_subsystem.open(index) {
+            Ok(controller) => {
+                self.show_status(&format!("connected {}", controller.name()));
+                self.controllers
+                    .insert(controller.instance_id(), controller);
+            }
+            Err(error) => eprintln!("Could not open game controller {}: {}", index, error),
+        }
+    }
+
+    // Releases the keys the controller was holding with its sticks
+    fn controller_removed(&mut self, id: u32, events: &mut Vec<Event>) {
+        if let Some(controller) = self.controllers.remove(&id) {
+            self.show_status(&format!("disconnected {}", controller.name()));
+        }
+        let axes: Vec<Axis> = self
+            .axes
+            .keys()
+            .filter(|&&(which, _)| which == id)
+            .map(|&(_, axis)| axis)
+            .collect();
+        for axis in axes {
+            self.axis_moved(id, axis, 0, events);
+        }
+        self.axes.retain(|&(which, _), _| which != id);
+    }
+
+    // A stick axis acts as two buttons, one per direction, pressed outside the deadzone
+    fn axis_moved(&mut self, id: u32, axis: Axis, value: i16, events: &mut Vec<Event>) {
+        let direction = match value {
+            value if value > self.deadzone => 1,
+            value if value < -self.deadzone => -1,
+            _ => 0,
+        };
+        let previous = self.axes.insert((id, axis), direction).unwrap_or(0);
+        if direction == previous {
+            return;
+        }
+
+        let name = |direction: i8| {
+            let sign = if direction > 0 { '+' } else { '-' };
+            format!("pad:{}{}", axis.string(), sign)
+        };
+        if previous != 0 {
+            events.extend(self.keymap.key(&name(previous)).map(Event::KeyUp));
+        }
+        if direction != 0 {
+            events.extend(self.keymap.key(&name(direction)).map(Event::KeyDown));
+        }
+    }
+}
+
+impl Display for SdlFrontend {
+    fn draw(&mut self, frame_buffer: &FrameBuffer) -> Result<(), String> {
+        let palette = self.palette;
+        let color = |pixel| {
+            let (r, g, b) = palette.rgb(pixel);
+            Color::RGB(r, g, b)
+        };
+
+        // The window is sized for lores. Each pixel is a whole number of canvas units, as
+        // close to a window pixel each as the scale allows, and SDL stretches the canvas
+        // over the window, so hires and odd scales still fill it.
+        let (width, height) = (frame_buffer.width(), frame_buffer.height());
+        let scale = (WIDTH as u32 * self.scale / width as u32).max(1);
+        let logical_size = (width as u32 * scale, height as u32 * scale);
+        if logical_size != self.logical_size {
+            self.canvas
+                .set_logical_size(logical_size.0, logical_size.1)
+                .map_err(|e| e.to_string())?;
+            self.logical_size = logical_size;
+        }
+        self.canvas.set_draw_color(color(0));
+        self.canvas.clear();
+
+        // The gap never hides a pixel completely
+        let size = scale.saturating_sub(self.grid).max(1);
+
+        for (index, pixel) in frame_buffer.pixels().enumerate() {
+            if pixel != 0 {
+                let (x, y) = (index % width, index / width);
+                self.canvas.set_draw_color(color(pixel));
+                let rect = Rect::new(
+                    (x as u32 * scale) as i32,
+                    (y as u32 * scale) as i32,
+                    size,
+                    size,
+                );
+                self.canvas.fill_rect(rect)?;
+            }
+        }
+        self.canvas.present();
+        Ok(())
+    }
+
+    fn show_status(&mut self, status: &str) {
+        let title = format!("CHIP-8 Emulator - {}", status);
+        let _ = self.canvas.window_mut().set_title(&title);
+    }
+}
+
+impl Audio for SdlFrontend {
+    fn update(&mut self, sound: &Sound) {
+        if !sound.playing || self.muted {
+            self.audio_queue.clear();
+            return;
+        }
+
+        let spec = self.audio_queue.spec();
+        let sample_rate = spec.freq as f64;
+        let channels = spec.channels as usize;
+        let amplitude = (8000.0 * self.volume) as i16; // Square waves are loud, keep well below 32767
+
+        // Keep a couple of frames of audio queued ahead of playback
+        let queued = self.audio_queue.size() as usize / (2 * channels);
+        let target = (sample_rate * TIMER_SPEED * 2.0) as usize;
+        if queued >= target {
+            return;
+        }
+
+        let step = sound.rate() / sample_rate;
+
+        let mut samples: Vec<i16> = Vec::with_capacity((target - queued) * channels);
+        for _ in queued..target {
+            let sample = if sound.bit(self.audio_position as usize) {
+                amplitude
+            } else {
+                -amplitude
+            };
+            samples.extend(std::iter::repeat_n(sample, channels));
+            self.audio_position = (self.audio_position + step) % 128.0;
+        }
+
+        let _ = self.audio_queue.queue_audio(&samples);
+    }
+}
+
+impl Frontend for SdlFrontend {
+    fn poll_events(&mut self) -> Vec<Event> {
+        let mut events = Vec::new();
+        let sdl_events: Vec<SdlEvent> = self.event_pump.poll_iter().collect();
+        for event in sdl_events {
+            match event {
+                // Holding a key sends repeats, which would press keypad keys and hotkeys again
+                // and end up in recorded movies
+                SdlEvent::KeyDown { repeat: true, .. } => {}
+                SdlEvent::Quit { .. }
+                | SdlEvent::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => events.push(Event::Quit),
+                SdlEvent::KeyDown {
+                    keycode: Some(Keycode::Space),
+                    ..
+                } => events.push(Event::Step),
+                SdlEvent::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } => events.push(Event::SaveState),
+                SdlEvent::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    ..
+                } => events.push(Event::NextSlot),
+                SdlEvent::KeyDown {
+                    keycode: Some(Keycode::F7),
+                    ..
+                } => events.push(Event::LoadState),
+                SdlEvent::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => events.push(Event::StartRewind),
+                SdlEvent::KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => events.push(Event::StopRewind),
+                SdlEvent::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } => events.extend(self.keymap.key(&keycode.name()).map(Event::KeyDown)),
+                SdlEvent::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => events.extend(self.keymap.key(&keycode.name()).map(Event::KeyUp)),
+                SdlEvent::ControllerButtonDown { button, .. } => {
+                    let name = format!("pad:{}", button.string());
+                    events.extend(self.keymap.key(&name).map(Event::KeyDown));
+                }
+                SdlEvent::ControllerButtonUp { button, .. } => {
+                    let name = format!("pad:{}", button.string());
+                    events.extend(self.keymap.key(&name).map(Event::KeyUp));
+                }
+                SdlEvent::ControllerAxisMotion {
+                    which, axis, value, ..
+                } => self.axis_moved(which, axis, value, &mut events),
+                SdlEvent::ControllerDeviceAdded { which, .. } => self.controller_added(which),
+                SdlEvent::ControllerDeviceRemoved { which, .. } => {
+                    self.controller_removed(which, &mut events)
+                }
+                _ => {}
+            }
+        }
+        events
+    }
+}
@@ -1 +1,4 @@
+pub mod config;
+pub mod database;
 pub mod emulator;
+pub mod frontend;
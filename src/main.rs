@@ -1,131 +1,506 @@
 use std::{
-    env,
-    fs::File,
+    fs,
+    io::{self, BufRead},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
     thread,
     time::{Duration, Instant},
 };
 
-use chip8_rs::emulator::{
-    chip8_context::{HEIGHT, LOOP_SPEED, SCALE, WIDTH},
-    emulator::{Chip8Emulator, EmulatorMode},
-};
-use sdl2::{
-    audio::{AudioQueue, AudioSpecDesired},
-    event::Event,
-    keyboard::Keycode,
+use chip8_rs::{
+    config::{Config, MAX_GRID, MAX_INSTRUCTIONS_PER_FRAME, MAX_SCALE, Settings},
+    database::{RomDatabase, detect_platform},
+    emulator::{
+        chip8_context::{DEFAULT_INSTRUCTIONS_PER_FRAME, TIMER_SPEED},
+        emulator::{self, Chip8Emulator, EmulatorMode, FrameOutcome},
+        error::{Chip8Error, StepOutcome},
+        gdb::GdbStub,
+        movie::{Movie, MoviePlayer},
+        quirks::Platform,
+        random::Generator,
+        rewind::Rewind,
+        sound::Sound,
+        timing::Timing,
+    },
+    frontend::{
+        Audio, Display, Event, Frontend,
+        keymap::Keymap,
+        palette::{Palette, parse_color},
+        sdl::{DEFAULT_SCALE, SdlFrontend},
+    },
 };
+use clap::{Parser, value_parser};
+
+const SLOTS: u32 = 10;
+
+// Default and longest length of the rewind history
+const REWIND_SECONDS: u32 = 10;
+const MAX_REWIND_SECONDS: u32 = 3600;
 
 fn main() -> Result<(), String> {
     // Init ROM
-    let args: Vec<String> = env::args().collect();
-    let romfile = args.get(1).expect("No ROM arg given");
-    let file = File::open(romfile).expect("ROM file not found");
+    let options = Cli::parse();
+    let romfile = &options.rom;
+    let rom = fs::read(romfile).map_err(|e| format!("{}: {}", romfile.display(), e))?;
 
-    let mut chip8 = Chip8Emulator::new(EmulatorMode::Run);
-    chip8
-        .read_rom_into_memory(file)
-        .expect("Could not read ROM into memory");
+    // Settings from the config file, the ROM's own section first. Flags override them.
+    let config = match options.config.clone().or_else(Config::default_path) {
+        Some(path) => Config::load(&path).map_err(|e| format!("{}: {}", path.display(), e))?,
+        None => Config::default(),
+    };
+    let rom_id = emulator::rom_id(&rom);
 
-    // Init sdl2
-    let sdl_context = sdl2::init()?;
-    let audio_subsystem = sdl_context.audio()?;
+    // Known ROMs configure themselves from the database, unknown ones get the platform their
+    // instructions need
+    let database = RomDatabase::builtin();
+    let info = database.lookup(&rom_id);
+    let known = match info {
+        Some(info) => {
+            println!("{}", info.title);
+            info.settings()
+        }
+        None => {
+            let platform = detect_platform(&rom);
+            if let Some(platform) = platform {
+                println!("ROM uses {} instructions", platform);
+            }
+            Settings {
+                platform,
+                ..Settings::default()
+            }
+        }
+    };
+    let settings = config.settings(&rom_id, known);
 
-    let audio_spec = AudioSpecDesired {
-        freq: Some(44100),
-        channels: Some(2),
-        samples: None,
+    let mode = if options.step {
+        EmulatorMode::Step
+    } else {
+        EmulatorMode::Run
     };
+    let mut chip8 = Chip8Emulator::new(mode);
+    // Quirks from the config or database only go with their platform, not one given as a flag
+    match options.platform {
+        Some(platform) => chip8.set_platform(platform),
+        None => {
+            if let Some(platform) = settings.platform {
+                chip8.set_platform(platform);
+            }
+            if let Some(quirks) = settings.quirks {
+                chip8.quirks = quirks;
+            }
+        }
+    }
+    chip8.timing = options.timing.or(settings.timing).unwrap_or_default();
+    chip8.instructions_per_frame = options
+        .ipf
+        .or(settings.ipf)
+        .unwrap_or(DEFAULT_INSTRUCTIONS_PER_FRAME);
+
+    // A fresh seed every run unless one is given, printed so a run can be reproduced
+    let seed = options.seed.unwrap_or_else(|| {
+        let seed = rand::random();
+        println!("Random seed {}", seed);
+        seed
+    });
+    chip8.seed_random(options.generator, seed);
 
-    let audio_queue: AudioQueue<i16> = audio_subsystem.open_queue(None, &audio_spec)?;
+    chip8
+        .load_rom(&rom)
+        .map_err(|e| format!("{}: {}", romfile.display(), e))?;
+
+    // A movie being played drives the keypad, one being recorded is saved on quit
+    let mut player = match &options.play {
+        Some(path) => {
+            let movie = Movie::load(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            movie.apply(&mut chip8).map_err(|e| e.to_string())?;
+            Some(MoviePlayer::new(movie))
+        }
+        None => None,
+    };
+    if options.record.is_some() {
+        chip8.recording = Some(Movie::new(&chip8, options.generator, seed));
+    }
 
-    audio_queue.resume();
+    // Init frontend
+    let scale = options.scale.or(settings.scale).unwrap_or(DEFAULT_SCALE);
+    let mut frontend = SdlFrontend::new(scale, options.fullscreen)?;
+    frontend.palette = palette(&options, &settings);
+    frontend.grid = options.grid.or(settings.grid).unwrap_or(0);
+    frontend.muted = match (options.mute, options.sound) {
+        (true, _) => true,
+        (_, true) => false,
+        _ => settings.mute.unwrap_or(false),
+    };
+    if let Some(volume) = settings.volume {
+        frontend.volume = volume.clamp(0.0, 1.0);
+    }
+    let mut keymap = Keymap::default();
+    if let Some(info) = info {
+        info.bind_keys(&mut keymap);
+        frontend.show_status(&info.title);
+    }
+    frontend.keymap = config.keymap(&rom_id, keymap).map_err(|e| e.to_string())?;
+    if let Some(deadzone) = config.deadzone {
+        frontend.deadzone = (deadzone.clamp(0.0, 1.0) * i16::MAX as f32) as i16;
+    }
 
-    // Loop
-    let interval = Duration::from_secs_f64(LOOP_SPEED);
+    // Save state slot selected with F6, starting at the one loaded
+    let mut slot = options.load_slot.unwrap_or(0);
+    if options.load_slot.is_some() {
+        let path = state_path(romfile, slot);
+        chip8
+            .load_state_from_file(&path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
 
-    let video_subsystem = sdl_context.video()?;
-    let window = video_subsystem
-        .window(
-            "CHIP-8 Emulator",
-            WIDTH as u32 * SCALE,
-            HEIGHT as u32 * SCALE,
-        )
-        .position_centered()
-        .build()
-        .map_err(|e| e.to_string())?;
+    // One state per frame, replayed backwards at the same speed while rewinding
+    let mut rewind = Rewind::new(options.rewind_seconds as usize);
+    let mut rewinding = false;
+    let frame_interval = Duration::from_secs_f64(TIMER_SPEED);
 
-    let mut canvas = window
-        .into_canvas()
-        .present_vsync()
-        .build()
-        .map_err(|e| e.to_string())?;
+    // Debugger commands typed on stdin while the window stays live
+    let commands = spawn_debugger_repl();
 
-    let mut event_pump = sdl_context.event_pump()?;
+    let mut gdb = match options.gdb_port {
+        Some(port) => {
+            let stub = GdbStub::listen(port).map_err(|e| format!("GDB server: {}", e))?;
+            if let Ok(address) = stub.local_addr() {
+                println!("GDB server listening on {}", address);
+            }
+            Some(stub)
+        }
+        None => None,
+    };
 
-    let mut last_loop = Instant::now();
+    // Loop, one iteration per 60 Hz frame. After a stall the schedule restarts instead of
+    // racing to catch up.
+    let mut next_frame = Instant::now();
 
     'running: loop {
         let now = Instant::now();
-        let elapsed = last_loop.elapsed();
 
-        if elapsed < interval {
-            thread::sleep(interval - elapsed);
+        if now < next_frame {
+            thread::sleep(next_frame - now);
             continue;
         }
 
-        last_loop = now;
+        if now - next_frame > frame_interval {
+            next_frame = now;
+        }
+        next_frame += frame_interval;
 
-        for event in event_pump.poll_iter() {
+        for event in frontend.poll_events() {
             match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                } => break 'running,
-                Event::KeyDown {
-                    keycode: Some(Keycode::Space),
-                    ..
-                } => {
+                Event::Quit => break 'running,
+                // Going back in time would desync a movie, and so would stepping, which lets
+                // keys change in the middle of a frame
+                Event::Step | Event::LoadState | Event::StartRewind
+                    if player.is_some() || chip8.recording.is_some() =>
+                {
+                    frontend.show_status("not while a movie runs");
+                }
+                Event::Step => {
+                    // Steps count towards frames like running does, so the timers keep time
                     if let EmulatorMode::Step = chip8.mode {
-                        chip8.execute_instruction();
-                        chip8.context.update_timers();
-                        chip8.audio(&audio_queue);
+                        match chip8.step_instruction() {
+                            Ok(StepOutcome::Exit) => break 'running,
+                            Ok(_) => {}
+                            Err(error) => halt(&mut chip8, &mut frontend, error),
+                        }
+                        println!("{}", chip8.location());
                     }
                 }
-                Event::KeyDown {
-                    keycode: Some(_), ..
-                } => {
-                    if let Event::KeyDown {
-                        keycode: Some(x), ..
-                    } = event
-                    {
-                        chip8.set_keydown(x);
+                Event::KeyDown(key) if player.is_none() => chip8.set_keydown(key),
+                Event::KeyUp(key) if player.is_none() => chip8.set_keyup(key),
+                Event::KeyDown(_) | Event::KeyUp(_) => {}
+                Event::SaveState => {
+                    let path = state_path(romfile, slot);
+                    match chip8.save_state_to_file(&path) {
+                        Ok(()) => frontend.show_status(&format!("saved slot {}", slot)),
+                        Err(error) => frontend.show_status(&format!("save failed: {}", error)),
                     }
                 }
-                Event::KeyUp {
-                    keycode: Some(_), ..
-                } => {
-                    if let Event::KeyUp {
-                        keycode: Some(x), ..
-                    } = event
-                    {
-                        chip8.set_keyup(x);
+                Event::LoadState => {
+                    let path = state_path(romfile, slot);
+                    match chip8.load_state_from_file(&path) {
+                        Ok(()) => frontend.show_status(&format!("loaded slot {}", slot)),
+                        Err(error) => frontend.show_status(&format!("load failed: {}", error)),
                     }
                 }
-                _ => {}
+                Event::NextSlot => {
+                    slot = (slot + 1) % SLOTS;
+                    frontend.show_status(&format!("slot {}", slot));
+                }
+                Event::StartRewind => rewinding = true,
+                Event::StopRewind => rewinding = false,
             }
         }
 
-        if let EmulatorMode::Run = chip8.mode {
-            chip8.execute_instruction();
-            chip8.context.update_timers();
-            chip8.audio(&audio_queue);
+        for line in commands.try_iter() {
+            let output = chip8.debug_command(&line);
+            if !output.is_empty() {
+                println!("{}", output);
+            }
+        }
+
+        if let Some(gdb) = gdb.as_mut() {
+            gdb.poll(&mut chip8);
+        }
+
+        // The program and its sound pause while rewinding. SUPER-CHIP programs can exit
+        // the interpreter.
+        if rewinding {
+            rewind.step_back(&mut chip8);
+            frontend.update(&Sound {
+                playing: false,
+                ..chip8.sound()
+            });
+        } else if let EmulatorMode::Run = chip8.mode {
+            if let Some(player) = player.as_mut() {
+                player.press_keys(&mut chip8);
+            }
+            rewind.push(&chip8);
+            match chip8.tick_frame() {
+                Ok(FrameOutcome::Complete) => {}
+                Ok(FrameOutcome::Stopped(reason)) => println!("{}\n{}", reason, chip8.location()),
+                Ok(FrameOutcome::Exit) => break 'running,
+                Err(error) => halt(&mut chip8, &mut frontend, error),
+            }
+            frontend.update(&chip8.sound());
+
+            // Pause on the last frame of a movie
+            if player
+                .as_ref()
+                .is_some_and(|player| player.is_finished(&chip8))
+            {
+                let movie = player.take().map(|player| player.movie);
+                report_playback(movie.as_ref(), &chip8, &mut frontend);
+                chip8.mode = EmulatorMode::Step;
+            }
         }
 
         if chip8.context.frame_buffer.is_dirty() {
-            chip8.context.frame_buffer.render(&mut canvas);
+            frontend.draw(&chip8.context.frame_buffer)?;
+            chip8.context.frame_buffer.mark_clean();
+        }
+    }
+
+    report_playback(
+        player.map(|player| player.movie).as_ref(),
+        &chip8,
+        &mut frontend,
+    );
+    if let (Some(mut movie), Some(path)) = (chip8.recording.take(), &options.record) {
+        movie.finish(&chip8);
+        match movie.save(path) {
+            Ok(()) => println!("Recorded {} frames to {}", movie.frames, path.display()),
+            Err(error) => eprintln!("Could not save {}: {}", path.display(), error),
         }
     }
 
     Ok(())
 }
+
+// Tells whether a movie ended in the state it was recorded with
+fn report_playback(movie: Option<&Movie>, chip8: &Chip8Emulator, frontend: &mut SdlFrontend) {
+    let Some(movie) = movie else { return };
+    let status = match movie.verify(chip8) {
+        Ok(()) => format!("playback matches after {} frames", chip8.frame()),
+        Err(error) => error.to_string(),
+    };
+    println!("{}", status);
+    frontend.show_status(&status);
+}
+
+// Command line options, `--help` lists them
+#[derive(Debug, Parser)]
+#[command(
+    name = "chip8-rs",
+    version,
+    about = "A CHIP-8, SUPER-CHIP and XO-CHIP emulator"
+)]
+struct Cli {
+    #[arg(help = "ROM file to run")]
+    rom: PathBuf,
+
+    #[arg(
+        long,
+        value_name = "PLATFORM",
+        help = "Quirks preset: chip8, chip48, schip or xochip [default: the behaviour modern ROMs expect]"
+    )]
+    platform: Option<Platform>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = value_parser!(u32).range(1..=MAX_INSTRUCTIONS_PER_FRAME as i64),
+        help = "Instructions run per 60 Hz frame with fixed timing [default: 11]"
+    )]
+    ipf: Option<u32>,
+
+    #[arg(long, help = "Instruction timing: fixed or vip [default: fixed]")]
+    timing: Option<Timing>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = value_parser!(u32).range(1..=MAX_SCALE as i64),
+        help = "Window pixels per CHIP-8 pixel [default: 10]"
+    )]
+    scale: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "THEME|COLOURS",
+        help = "Colour palette: mono, inverted, amber, green, lcd, octo, or up to four \
+                colours like 000000,FFFFFF,AAAAAA,555555 for the background, plane 1, \
+                plane 2 and both planes [default: mono]"
+    )]
+    palette: Option<Palette>,
+
+    #[arg(
+        long = "fg",
+        value_name = "RRGGBB",
+        value_parser = parse_color,
+        help = "Foreground colour, replacing the palette's"
+    )]
+    foreground: Option<u32>,
+
+    #[arg(
+        long = "bg",
+        value_name = "RRGGBB",
+        value_parser = parse_color,
+        help = "Background colour, replacing the palette's"
+    )]
+    background: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "PIXELS",
+        value_parser = value_parser!(u32).range(0..=MAX_GRID as i64),
+        help = "Gap between pixels, in window pixels [default: 0]"
+    )]
+    grid: Option<u32>,
+
+    #[arg(long, help = "Fill the screen")]
+    fullscreen: bool,
+
+    #[arg(long, overrides_with = "sound", help = "Turn sound off")]
+    mute: bool,
+
+    #[arg(
+        long,
+        overrides_with = "mute",
+        help = "Turn sound on when the config mutes it"
+    )]
+    sound: bool,
+
+    #[arg(
+        long,
+        visible_alias = "paused",
+        help = "Start paused, Space runs one instruction"
+    )]
+    step: bool,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Random seed [default: a new one every run]"
+    )]
+    seed: Option<u64>,
+
+    #[arg(
+        long = "rng",
+        value_name = "GENERATOR",
+        default_value = "xorshift",
+        help = "Random generator: xorshift or vip-style"
+    )]
+    generator: Generator,
+
+    #[arg(
+        long,
+        value_name = "SLOT",
+        value_parser = value_parser!(u32).range(0..SLOTS as i64),
+        conflicts_with_all = ["record", "play"],
+        help = "Load a save state slot, 0 to 9, at start"
+    )]
+    load_slot: Option<u32>,
+
+    #[arg(
+        long = "rewind",
+        value_name = "SECONDS",
+        default_value_t = REWIND_SECONDS,
+        help = "Seconds of rewind history, 0 turns rewinding off",
+        value_parser = value_parser!(u32).range(0..=MAX_REWIND_SECONDS as i64)
+    )]
+    rewind_seconds: u32,
+
+    #[arg(
+        long = "gdb",
+        value_name = "PORT",
+        help = "Listen for GDB on a TCP port"
+    )]
+    gdb_port: Option<u16>,
+
+    #[arg(long, value_name = "MOVIE", help = "Record the keypad to a movie file")]
+    record: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "MOVIE",
+        conflicts_with = "record",
+        help = "Replay a movie file"
+    )]
+    play: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Config file [default: config.toml in the user config directory]"
+    )]
+    config: Option<PathBuf>,
+}
+
+// The palette from the config or database, with its colours given on the command line
+// replaced. A palette given on the command line replaces the config's colours too.
+fn palette(options: &Cli, settings: &Settings) -> Palette {
+    let mut palette = match options.palette {
+        Some(palette) => palette,
+        None => {
+            let mut palette = settings.palette.unwrap_or_default();
+            palette.colors[0] = settings.background.unwrap_or(palette.colors[0]);
+            palette.colors[1] = settings.foreground.unwrap_or(palette.colors[1]);
+            palette
+        }
+    };
+    palette.colors[0] = options.background.unwrap_or(palette.colors[0]);
+    palette.colors[1] = options.foreground.unwrap_or(palette.colors[1]);
+    palette
+}
+
+// Reads debugger commands on a separate thread, so the emulator loop never blocks on stdin
+fn spawn_debugger_repl() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+// A fault halts the emulator and is shown to the user instead of aborting, so the last frame
+// stays on screen
+fn halt(chip8: &mut Chip8Emulator, frontend: &mut SdlFrontend, error: Chip8Error) {
+    eprintln!("{}", error);
+    frontend.show_status(&format!("halted: {}", error));
+    chip8.mode = EmulatorMode::Halted;
+}
+
+// Save states live next to the ROM, game.ch8 uses game.state0 to game.state9
+fn state_path(romfile: &Path, slot: u32) -> PathBuf {
+    romfile.with_extension(format!("state{}", slot))
+}
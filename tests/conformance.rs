@@ -0,0 +1,368 @@
+// Runs the test ROMs in tests/roms through the headless core and checks the final screen.
+// The opcode, flags and quirks ROMs report every check as a cell of two hex digits and a
+// tick or a cross, which are read back here and compared with the results the CHIP-8
+// references give. The IBM logo and keypad screens are drawn here from the sprites the
+// ROMs use. None of the expectations come from the emulator's own output.
+
+use std::path::PathBuf;
+
+use chip8_rs::emulator::{
+    assembler::assemble_file,
+    emulator::{Chip8Emulator, EmulatorMode, FrameOutcome},
+    headless::{HeadlessRunner, KeyPress, Stop},
+    movie::{Movie, MoviePlayer},
+    quirks::Platform,
+    random::Generator,
+    timing::Timing,
+};
+
+const MAX_FRAMES: u64 = 600;
+
+// Fast enough that the display wait quirk is the only thing limiting draws per frame
+const INSTRUCTIONS_PER_FRAME: u32 = 1000;
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+
+// The hex digits as given in Cowgod's CHIP-8 technical reference
+const DIGITS: [[u8; 5]; 16] = [
+    [0xF0, 0x90, 0x90, 0x90, 0xF0],
+    [0x20, 0x60, 0x20, 0x20, 0x70],
+    [0xF0, 0x10, 0xF0, 0x80, 0xF0],
+    [0xF0, 0x10, 0xF0, 0x10, 0xF0],
+    [0x90, 0x90, 0xF0, 0x10, 0x10],
+    [0xF0, 0x80, 0xF0, 0x10, 0xF0],
+    [0xF0, 0x80, 0xF0, 0x90, 0xF0],
+    [0xF0, 0x10, 0x20, 0x40, 0x40],
+    [0xF0, 0x90, 0xF0, 0x90, 0xF0],
+    [0xF0, 0x90, 0xF0, 0x10, 0xF0],
+    [0xF0, 0x90, 0xF0, 0x90, 0x90],
+    [0xE0, 0x90, 0xE0, 0x90, 0xE0],
+    [0xF0, 0x80, 0x80, 0x80, 0xF0],
+    [0xE0, 0x90, 0x90, 0x90, 0xE0],
+    [0xF0, 0x80, 0xF0, 0x80, 0xF0],
+    [0xF0, 0x80, 0xF0, 0x80, 0x80],
+];
+
+// The marks report.inc draws after each result
+const TICK: [u8; 5] = [0x08, 0x10, 0xA0, 0x40, 0x00];
+const CROSS: [u8; 5] = [0x88, 0x50, 0x20, 0x50, 0x88];
+
+fn test_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+fn test_rom(name: &str) -> Vec<u8> {
+    let source = test_dir().join("roms").join(format!("{}.asm", name));
+    assemble_file(&source).unwrap_or_else(|error| panic!("{}", error))
+}
+
+// Assembles and runs a test ROM until it ends in its final loop, returning the screen
+fn run_rom(name: &str, platform: Option<Platform>, keys: &[KeyPress]) -> Screen {
+    run_rom_timed(name, platform, Timing::Fixed, keys)
+}
+
+fn run_rom_timed(
+    name: &str,
+    platform: Option<Platform>,
+    timing: Timing,
+    keys: &[KeyPress],
+) -> Screen {
+    let mut chip8 = Chip8Emulator::new(EmulatorMode::Run);
+    if let Some(platform) = platform {
+        chip8.set_platform(platform);
+    }
+    chip8.timing = timing;
+    chip8.instructions_per_frame = INSTRUCTIONS_PER_FRAME;
+    chip8
+        .load_rom(&test_rom(name))
+        .expect("test ROM fits in memory");
+
+    let mut runner = HeadlessRunner::new(chip8);
+    runner.keys = keys.to_vec();
+
+    let stop = runner.run(None, Some(MAX_FRAMES));
+    assert!(
+        matches!(stop, Stop::Loop { .. }),
+        "{} did not finish: {}\n{}",
+        name,
+        stop,
+        runner.chip8.registers()
+    );
+
+    Screen::parse(&runner.screen_ascii())
+}
+
+// A low resolution screen, pixels lit in any plane are set
+#[derive(PartialEq, Eq)]
+struct Screen {
+    rows: Vec<Vec<bool>>,
+}
+
+impl Screen {
+    fn blank() -> Self {
+        Screen {
+            rows: vec![vec![false; WIDTH]; HEIGHT],
+        }
+    }
+
+    fn parse(ascii: &str) -> Self {
+        let rows: Vec<Vec<bool>> = ascii
+            .lines()
+            .map(|line| line.chars().map(|c| c != '.').collect())
+            .collect();
+        assert!(
+            rows.len() == HEIGHT && rows.iter().all(|row| row.len() == WIDTH),
+            "not a low resolution screen:\n{}",
+            ascii
+        );
+        Screen { rows }
+    }
+
+    // XORs a sprite onto the screen like DXYN, without wrapping
+    fn draw(&mut self, x: usize, y: usize, sprite: &[u8]) {
+        for (row, bits) in self.rows[y..].iter_mut().zip(sprite) {
+            for (column, pixel) in row.iter_mut().skip(x).take(8).enumerate() {
+                *pixel ^= bits & (0x80 >> column) != 0;
+            }
+        }
+    }
+
+    // The sprite rows that would have drawn the 8x5 area at x, y, masked to a glyph's width
+    fn sprite(&self, x: usize, y: usize, mask: u8) -> [u8; 5] {
+        let mut sprite = [0; 5];
+        for (bits, row) in sprite.iter_mut().zip(&self.rows[y..]) {
+            for column in 0..8 {
+                if row.get(x + column).copied().unwrap_or(false) {
+                    *bits |= 0x80 >> column;
+                }
+            }
+            *bits &= mask;
+        }
+        sprite
+    }
+
+    // The cells report.inc draws: two hex digits and a mark, four cells to a row, in the
+    // order they were reported
+    fn results(&self) -> Vec<(u8, bool)> {
+        let mut results = Vec::new();
+        for y in (0..HEIGHT - 4).step_by(6) {
+            for x in (0..WIDTH).step_by(16) {
+                let glyphs = [
+                    self.sprite(x, y, 0xF0),
+                    self.sprite(x + 5, y, 0xF0),
+                    self.sprite(x + 10, y, 0xF8),
+                ];
+                if glyphs.iter().flatten().all(|&bits| bits == 0) {
+                    return results;
+                }
+                let digit = |glyph| {
+                    DIGITS
+                        .iter()
+                        .position(|digit| *digit == glyph)
+                        .unwrap_or_else(|| panic!("no digit at {}, {}\n{}", x, y, self))
+                        as u8
+                };
+                let passed = match glyphs[2] {
+                    TICK => true,
+                    CROSS => false,
+                    _ => panic!("no mark at {}, {}\n{}", x + 10, y, self),
+                };
+                results.push((digit(glyphs[0]) << 4 | digit(glyphs[1]), passed));
+            }
+        }
+        results
+    }
+}
+
+impl std::fmt::Display for Screen {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for row in &self.rows {
+            let line: String = row.iter().map(|&lit| if lit { '#' } else { '.' }).collect();
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+fn assert_screen(actual: &Screen, expected: &Screen) {
+    assert!(
+        actual == expected,
+        "expected:\n{}\nactual:\n{}",
+        expected,
+        actual
+    );
+}
+
+// Every check a ROM reports passed, under the given labels
+fn assert_all_passed(screen: &Screen, labels: &[u8]) {
+    let expected: Vec<(u8, bool)> = labels.iter().map(|&label| (label, true)).collect();
+    assert_eq!(screen.results(), expected, "\n{}", screen);
+}
+
+#[test]
+fn ibm_logo() {
+    let mut expected = Screen::blank();
+    let letters: [[u8; 13]; 3] = [
+        [0x7E, 0, 0x18, 0, 0x18, 0, 0x18, 0, 0x18, 0, 0x18, 0, 0x7E],
+        [0xFC, 0, 0xC6, 0, 0xC6, 0, 0xFC, 0, 0xC6, 0, 0xC6, 0, 0xFC],
+        [0xC3, 0, 0xE7, 0, 0xFF, 0, 0xDB, 0, 0xC3, 0, 0xC3, 0, 0xC3],
+    ];
+    for (index, letter) in letters.iter().enumerate() {
+        expected.draw(20 + 9 * index, 9, letter);
+    }
+    assert_screen(&run_rom("ibm", None, &[]), &expected);
+}
+
+#[test]
+fn opcodes() {
+    let labels = [
+        0x30, 0x40, 0x50, 0x90, 0x70, 0x80, 0x81, 0x82, 0x83, 0x84, 0x85, 0x87, 0x86, 0x8E, 0xFE,
+        0x33, 0x55, 0x65, 0xD1, 0xD0,
+    ];
+    assert_all_passed(&run_rom("opcodes", None, &[]), &labels);
+}
+
+#[test]
+fn flags() {
+    let labels = [
+        0x41, 0x42, 0x43, 0x44, 0x51, 0x52, 0x53, 0x54, 0x71, 0x72, 0x73, 0x74, 0x61, 0x62, 0x63,
+        0x64, 0xE1, 0xE2, 0xE3, 0xE4,
+    ];
+    assert_all_passed(&run_rom("flags", None, &[]), &labels);
+}
+
+// The quirks a platform should show, in the ROM's order: VF reset, memory increment,
+// display wait, wrapping, shift and jump. From the table in Timendus' quirks test, with
+// CHIP-48 behaving like SUPER-CHIP 1.1 for these.
+fn expected_quirks(platform: Option<Platform>) -> Vec<(u8, bool)> {
+    let active = match platform {
+        None => [false; 6],
+        Some(Platform::Chip8) => [true, true, true, false, false, false],
+        Some(Platform::Chip48 | Platform::SuperChip) => [false, false, false, false, true, true],
+        Some(Platform::XoChip) => [false, true, false, true, false, false],
+    };
+    (1..).zip(active).collect()
+}
+
+#[test]
+fn quirks_default() {
+    let screen = run_rom("quirks", None, &[]);
+    assert_eq!(screen.results(), expected_quirks(None), "\n{}", screen);
+}
+
+#[test]
+fn quirks_per_platform() {
+    for platform in Platform::ALL {
+        let screen = run_rom("quirks", Some(platform), &[]);
+        assert_eq!(
+            screen.results(),
+            expected_quirks(Some(platform)),
+            "{}\n{}",
+            platform,
+            screen
+        );
+    }
+}
+
+// Cycle counting must not change what the VIP quirks draw
+#[test]
+fn vip_timing() {
+    let platform = Some(Platform::Chip8);
+    let screen = run_rom_timed("quirks", platform, Timing::CosmacVip, &[]);
+    assert_eq!(screen.results(), expected_quirks(platform), "\n{}", screen);
+}
+
+#[test]
+fn keypad() {
+    let keys = [
+        KeyPress {
+            frame: 10,
+            key: 0xA,
+            frames: 6,
+        },
+        KeyPress {
+            frame: 30,
+            key: 0x5,
+            frames: 10,
+        },
+    ];
+
+    // The key FX0A returned, the key EX9E saw held, then E once EXA1 saw it released
+    let mut expected = Screen::blank();
+    for (x, digit) in [(0, 0xA), (6, 0x5), (12, 0xE)] {
+        expected.draw(x, 0, &DIGITS[digit]);
+    }
+    assert_screen(&run_rom("keypad", None, &keys), &expected);
+}
+
+// Frames only depend on the keys pressed in them, never on the host clock
+#[test]
+fn frames_are_deterministic() {
+    let rom = test_rom("keypad");
+
+    let run = || {
+        let mut chip8 = Chip8Emulator::new(EmulatorMode::Run);
+        chip8.load_rom(&rom).expect("test ROM fits in memory");
+        for frame in 0..120 {
+            match frame {
+                10 => chip8.set_keydown(0xA),
+                16 => chip8.set_keyup(0xA),
+                30 => chip8.set_keydown(0x5),
+                40 => chip8.set_keyup(0x5),
+                _ => {}
+            }
+            assert_eq!(chip8.tick_frame(), Ok(FrameOutcome::Complete));
+        }
+        chip8.save_state()
+    };
+
+    assert_eq!(run(), run());
+}
+
+// A recorded movie replays to the same end state, and an edited one is caught
+#[test]
+fn movie_replays() {
+    let rom = test_rom("keypad");
+    let emulator = || {
+        let mut chip8 = Chip8Emulator::new(EmulatorMode::Run);
+        chip8.load_rom(&rom).expect("test ROM fits in memory");
+        chip8
+    };
+
+    let mut chip8 = emulator();
+    chip8.seed_random(Generator::Xorshift, 42);
+    chip8.recording = Some(Movie::new(&chip8, Generator::Xorshift, 42));
+    for frame in 0..90 {
+        match frame {
+            10 => chip8.set_keydown(0xA),
+            16 => chip8.set_keyup(0xA),
+            30 => chip8.set_keydown(0x5),
+            40 => chip8.set_keyup(0x5),
+            _ => {}
+        }
+        chip8.tick_frame().unwrap();
+    }
+    let mut movie = chip8.recording.take().unwrap();
+    movie.finish(&chip8);
+    assert_eq!(movie.events.len(), 4);
+
+    let play = |text: &str| {
+        let movie = Movie::parse(text).unwrap();
+        let mut chip8 = emulator();
+        movie.apply(&mut chip8).unwrap();
+        let mut player = MoviePlayer::new(movie);
+        while !player.is_finished(&chip8) {
+            player.press_keys(&mut chip8);
+            chip8.tick_frame().unwrap();
+        }
+        player.movie.verify(&chip8)
+    };
+
+    let text = movie.to_text();
+    assert!(play(&text).is_ok());
+    assert!(play(&text.replace("30 down 5\n", "")).is_err());
+
+    let truncated = text.replace(&format!("frames {}\n", movie.frames), "");
+    assert!(Movie::parse(&truncated).is_err());
+}
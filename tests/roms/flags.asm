@@ -0,0 +1,254 @@
+; Flags test in the style of Timendus' flags test. Each row checks one of 8XY4, 8XY5,
+; 8XY7, 8XY6 and 8XYE: the result and VF for both values of the flag, then VF used as an
+; operand, where the flag has to be written after the result. Shifts use VX as VY, so
+; the results do not depend on the shift quirk.
+
+    CLS
+    LD VE, 0
+    LD VD, 0
+
+; 8XY4: 0x10 + 0x20 sets VF to 0
+    LD VF, 0x55
+    LD V0, 0x10
+    LD V1, 0x20
+    ADD V0, V1
+    LD VC, 1
+    SE V0, 0x30
+    LD VC, 0
+    SE VF, 0x00
+    LD VC, 0
+    LD VA, 0x4
+    LD VB, 1
+    CALL report
+
+; 8XY4: 0xF0 + 0x20 sets VF to 1
+    LD V0, 0xF0
+    LD V1, 0x20
+    ADD V0, V1
+    LD VC, 1
+    SE V0, 0x10
+    LD VC, 0
+    SE VF, 0x01
+    LD VC, 0
+    LD VA, 0x4
+    LD VB, 2
+    CALL report
+
+; 8XY4: VF as VX ends up holding the flag
+    LD VF, 0xF0
+    LD V1, 0x20
+    ADD VF, V1
+    LD VC, 1
+    SE VF, 0x01
+    LD VC, 0
+    LD VA, 0x4
+    LD VB, 3
+    CALL report
+
+; 8XY4: VF as VY
+    LD V0, 0xF0
+    LD VF, 0x20
+    ADD V0, VF
+    LD VC, 1
+    SE V0, 0x10
+    LD VC, 0
+    SE VF, 0x01
+    LD VC, 0
+    LD VA, 0x4
+    LD VB, 4
+    CALL report
+
+; 8XY5: 0x30 - 0x10 sets VF to 1
+    LD V0, 0x30
+    LD V1, 0x10
+    SUB V0, V1
+    LD VC, 1
+    SE V0, 0x20
+    LD VC, 0
+    SE VF, 0x01
+    LD VC, 0
+    LD VA, 0x5
+    LD VB, 1
+    CALL report
+
+; 8XY5: 0x10 - 0x30 sets VF to 0
+    LD V0, 0x10
+    LD V1, 0x30
+    SUB V0, V1
+    LD VC, 1
+    SE V0, 0xE0
+    LD VC, 0
+    SE VF, 0x00
+    LD VC, 0
+    LD VA, 0x5
+    LD VB, 2
+    CALL report
+
+; 8XY5: VF as VX ends up holding the flag
+    LD VF, 0x10
+    LD V1, 0x30
+    SUB VF, V1
+    LD VC, 1
+    SE VF, 0x00
+    LD VC, 0
+    LD VA, 0x5
+    LD VB, 3
+    CALL report
+
+; 8XY5: VF as VY
+    LD V0, 0x30
+    LD VF, 0x10
+    SUB V0, VF
+    LD VC, 1
+    SE V0, 0x20
+    LD VC, 0
+    SE VF, 0x01
+    LD VC, 0
+    LD VA, 0x5
+    LD VB, 4
+    CALL report
+
+; 8XY7: 0x30 - 0x10 sets VF to 1
+    LD V0, 0x10
+    LD V1, 0x30
+    SUBN V0, V1
+    LD VC, 1
+    SE V0, 0x20
+    LD VC, 0
+    SE VF, 0x01
+    LD VC, 0
+    LD VA, 0x7
+    LD VB, 1
+    CALL report
+
+; 8XY7: 0x10 - 0x30 sets VF to 0
+    LD V0, 0x30
+    LD V1, 0x10
+    SUBN V0, V1
+    LD VC, 1
+    SE V0, 0xE0
+    LD VC, 0
+    SE VF, 0x00
+    LD VC, 0
+    LD VA, 0x7
+    LD VB, 2
+    CALL report
+
+; 8XY7: VF as VX ends up holding the flag
+    LD VF, 0x30
+    LD V1, 0x10
+    SUBN VF, V1
+    LD VC, 1
+    SE VF, 0x00
+    LD VC, 0
+    LD VA, 0x7
+    LD VB, 3
+    CALL report
+
+; 8XY7: VF as VY
+    LD V0, 0x10
+    LD VF, 0x30
+    SUBN V0, VF
+    LD VC, 1
+    SE V0, 0x20
+    LD VC, 0
+    SE VF, 0x01
+    LD VC, 0
+    LD VA, 0x7
+    LD VB, 4
+    CALL report
+
+; 8XY6: 0x04 >> 1 sets VF to 0
+    LD VF, 0x55
+    LD V0, 0x04
+    SHR V0
+    LD VC, 1
+    SE V0, 0x02
+    LD VC, 0
+    SE VF, 0x00
+    LD VC, 0
+    LD VA, 0x6
+    LD VB, 1
+    CALL report
+
+; 8XY6: 0x05 >> 1 sets VF to 1
+    LD V0, 0x05
+    SHR V0
+    LD VC, 1
+    SE V0, 0x02
+    LD VC, 0
+    SE VF, 0x01
+    LD VC, 0
+    LD VA, 0x6
+    LD VB, 2
+    CALL report
+
+; 8XY6: VF as VX ends up holding the flag, 1
+    LD VF, 0x05
+    SHR VF
+    LD VC, 1
+    SE VF, 0x01
+    LD VC, 0
+    LD VA, 0x6
+    LD VB, 3
+    CALL report
+
+; 8XY6: VF as VX ends up holding the flag, 0
+    LD VF, 0x04
+    SHR VF
+    LD VC, 1
+    SE VF, 0x00
+    LD VC, 0
+    LD VA, 0x6
+    LD VB, 4
+    CALL report
+
+; 8XYE: 0x41 << 1 sets VF to 0
+    LD VF, 0x55
+    LD V0, 0x41
+    SHL V0
+    LD VC, 1
+    SE V0, 0x82
+    LD VC, 0
+    SE VF, 0x00
+    LD VC, 0
+    LD VA, 0xE
+    LD VB, 1
+    CALL report
+
+; 8XYE: 0x81 << 1 sets VF to 1
+    LD V0, 0x81
+    SHL V0
+    LD VC, 1
+    SE V0, 0x02
+    LD VC, 0
+    SE VF, 0x01
+    LD VC, 0
+    LD VA, 0xE
+    LD VB, 2
+    CALL report
+
+; 8XYE: VF as VX ends up holding the flag, 1
+    LD VF, 0x81
+    SHL VF
+    LD VC, 1
+    SE VF, 0x01
+    LD VC, 0
+    LD VA, 0xE
+    LD VB, 3
+    CALL report
+
+; 8XYE: VF as VX ends up holding the flag, 0
+    LD VF, 0x41
+    SHL VF
+    LD VC, 1
+    SE VF, 0x00
+    LD VC, 0
+    LD VA, 0xE
+    LD VB, 4
+    CALL report
+
+end:
+    JP end
+
+    INCLUDE "report.inc"
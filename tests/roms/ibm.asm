@@ -0,0 +1,24 @@
+; Draws a striped IBM logo, the classic first test of DXYN
+
+    CLS
+    LD V0, 20
+    LD V1, 9
+    LD I, letter_i
+    DRW V0, V1, 13
+    ADD V0, 9
+    LD I, letter_b
+    DRW V0, V1, 13
+    ADD V0, 9
+    LD I, letter_m
+    DRW V0, V1, 13
+end:
+    JP end
+
+letter_i:
+    DB 0x7E, 0, 0x18, 0, 0x18, 0, 0x18, 0, 0x18, 0, 0x18, 0, 0x7E
+
+letter_b:
+    DB 0xFC, 0, 0xC6, 0, 0xC6, 0, 0xFC, 0, 0xC6, 0, 0xC6, 0, 0xFC
+
+letter_m:
+    DB 0xC3, 0, 0xE7, 0, 0xFF, 0, 0xDB, 0, 0xC3, 0, 0xC3, 0, 0xC3
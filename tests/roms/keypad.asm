@@ -0,0 +1,31 @@
+; Keypad test. The runner presses A, then holds 5 for a while:
+;   FX0A waits for A to be pressed and released and draws it
+;   EX9E polls until 5 is held and draws it
+;   EXA1 polls until 5 is released and draws E
+
+    CLS
+    LD V2, 0
+    LD V3, 0
+
+    LD V0, K
+    LD F, V0
+    DRW V2, V3, 5
+
+    LD V1, 5
+held:
+    SKP V1
+    JP held
+    ADD V2, 6
+    LD F, V1
+    DRW V2, V3, 5
+
+released:
+    SKNP V1
+    JP released
+    ADD V2, 6
+    LD V0, 0xE
+    LD F, V0
+    DRW V2, V3, 5
+
+end:
+    JP end
@@ -0,0 +1,262 @@
+; Opcode test in the style of corax+. Every cell is labelled with the opcode's first
+; and last nibble and shows a tick when the instruction behaved correctly.
+
+    CLS
+    LD VE, 0
+    LD VD, 0
+
+; 3XNN skips when equal
+    LD VA, 3
+    LD VB, 0
+    LD V0, 0x42
+    LD VC, 1
+    SE V0, 0x42
+    LD VC, 0
+    CALL report
+
+; 4XNN skips when not equal
+    LD VA, 4
+    LD VB, 0
+    LD VC, 1
+    SNE V0, 0x43
+    LD VC, 0
+    CALL report
+
+; 5XY0 skips when equal
+    LD VA, 5
+    LD VB, 0
+    LD V1, 0x42
+    LD VC, 1
+    SE V0, V1
+    LD VC, 0
+    CALL report
+
+; 9XY0 skips when not equal
+    LD VA, 9
+    LD VB, 0
+    LD V1, 0x43
+    LD VC, 1
+    SNE V0, V1
+    LD VC, 0
+    CALL report
+
+; 7XNN wraps around and leaves VF alone
+    LD VA, 7
+    LD VB, 0
+    LD VF, 0x55
+    LD V0, 0xFF
+    ADD V0, 2
+    LD VC, 1
+    SE V0, 1
+    LD VC, 0
+    SE VF, 0x55
+    LD VC, 0
+    CALL report
+
+; 8XY0
+    LD VA, 8
+    LD VB, 0
+    LD V1, 0x33
+    LD V0, V1
+    LD VC, 1
+    SE V0, 0x33
+    LD VC, 0
+    CALL report
+
+; 8XY1
+    LD VA, 8
+    LD VB, 1
+    LD V0, 0x0F
+    LD V1, 0xF0
+    OR V0, V1
+    LD VC, 1
+    SE V0, 0xFF
+    LD VC, 0
+    CALL report
+
+; 8XY2
+    LD VA, 8
+    LD VB, 2
+    LD V0, 0x3C
+    LD V1, 0x0F
+    AND V0, V1
+    LD VC, 1
+    SE V0, 0x0C
+    LD VC, 0
+    CALL report
+
+; 8XY3
+    LD VA, 8
+    LD VB, 3
+    LD V0, 0x3C
+    LD V1, 0x0F
+    XOR V0, V1
+    LD VC, 1
+    SE V0, 0x33
+    LD VC, 0
+    CALL report
+
+; 8XY4 with carry
+    LD VA, 8
+    LD VB, 4
+    LD V0, 0xFF
+    LD V1, 0x02
+    ADD V0, V1
+    LD VC, 1
+    SE V0, 0x01
+    LD VC, 0
+    SE VF, 1
+    LD VC, 0
+    CALL report
+
+; 8XY5 without borrow
+    LD VA, 8
+    LD VB, 5
+    LD V0, 0x30
+    LD V1, 0x10
+    SUB V0, V1
+    LD VC, 1
+    SE V0, 0x20
+    LD VC, 0
+    SE VF, 1
+    LD VC, 0
+    CALL report
+
+; 8XY7 with borrow
+    LD VA, 8
+    LD VB, 7
+    LD V0, 0x30
+    LD V1, 0x10
+    SUBN V0, V1
+    LD VC, 1
+    SE V0, 0xE0
+    LD VC, 0
+    SE VF, 0
+    LD VC, 0
+    CALL report
+
+; 8XY6 shifts right, VF gets the bit shifted out
+    LD VA, 8
+    LD VB, 6
+    LD V0, 0x05
+    SHR V0
+    LD VC, 1
+    SE V0, 0x02
+    LD VC, 0
+    SE VF, 1
+    LD VC, 0
+    CALL report
+
+; 8XYE shifts left, VF gets the bit shifted out
+    LD VA, 8
+    LD VB, 0xE
+    LD V0, 0x81
+    SHL V0
+    LD VC, 1
+    SE V0, 0x02
+    LD VC, 0
+    SE VF, 1
+    LD VC, 0
+    CALL report
+
+; FX1E
+    LD VA, 0xF
+    LD VB, 0xE
+    LD I, data
+    LD V0, 2
+    ADD I, V0
+    LD V0, [I]
+    LD VC, 1
+    SE V0, 0x33
+    LD VC, 0
+    CALL report
+
+; FX33
+    LD VA, 3
+    LD VB, 3
+    LD I, scratch
+    LD V0, 137
+    LD B, V0
+    LD V2, [I]
+    LD VC, 1
+    SE V0, 1
+    LD VC, 0
+    SE V1, 3
+    LD VC, 0
+    SE V2, 7
+    LD VC, 0
+    CALL report
+
+; FX55 stores V0 to VX
+    LD VA, 5
+    LD VB, 5
+    LD V0, 0xA0
+    LD V1, 0xA1
+    LD V2, 0xA2
+    LD I, scratch
+    LD [I], V1
+    LD I, scratch
+    LD V3, [I]
+    LD VC, 1
+    SE V0, 0xA0
+    LD VC, 0
+    SE V1, 0xA1
+    LD VC, 0
+    SE V2, 7
+    LD VC, 0
+    CALL report
+
+; FX65 loads V0 to VX
+    LD VA, 6
+    LD VB, 5
+    LD V2, 0
+    LD I, data
+    LD V1, [I]
+    LD VC, 1
+    SE V0, 0x11
+    LD VC, 0
+    SE V1, 0x22
+    LD VC, 0
+    SE V2, 0
+    LD VC, 0
+    CALL report
+
+; DXYN sets VF on collision and erases the pixel
+    LD VA, 0xD
+    LD VB, 1
+    LD V0, 60
+    LD V1, 31
+    LD I, dot
+    DRW V0, V1, 1
+    DRW V0, V1, 1
+    LD VC, 1
+    SE VF, 1
+    LD VC, 0
+    DRW V0, V1, 1
+    DRW V0, V1, 1
+    CALL report
+
+; DXYN clears VF without a collision
+    LD VA, 0xD
+    LD VB, 0
+    LD VF, 1
+    DRW V0, V1, 1
+    LD VC, 1
+    SE VF, 0
+    LD VC, 0
+    DRW V0, V1, 1
+    CALL report
+
+end:
+    JP end
+
+data:
+    DB 0x11, 0x22, 0x33
+
+dot:
+    DB 0x80
+
+scratch:
+    DB 0, 0, 0
+
+    INCLUDE "report.inc"
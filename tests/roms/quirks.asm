@@ -0,0 +1,121 @@
+; Quirks test in the style of Timendus' quirks test. Detects which behaviour the
+; emulator picked for each ambiguous instruction, then shows one cell per quirk with
+; a tick when the quirk is active:
+;   01  VF reset by 8XY1, 8XY2 and 8XY3
+;   02  FX55 and FX65 increment I
+;   03  DXYN waits for vertical blank
+;   04  sprites wrap around the screen edge instead of being clipped
+;   05  8XY6 and 8XYE shift VX instead of VY
+;   06  BNNN jumps to XNN + VX instead of NNN + V0
+; The results are kept in V3 to V8 until all tests ran.
+
+; Jump, first so that the jump target stays within 0x2NN
+    LD V0, 0
+    LD V2, 2
+    JP V0, jump_table
+jump_table:
+    JP jump_v0
+    JP jump_vx
+jump_v0:
+    LD V8, 0
+    JP jump_done
+jump_vx:
+    LD V8, 1
+jump_done:
+
+; VF reset
+    LD VF, 0x55
+    LD V0, 0x0F
+    LD V1, 0xF0
+    OR V0, V1
+    LD V3, 0
+    SNE VF, 0
+    LD V3, 1
+
+; Memory
+    LD I, buffer
+    LD V0, 0x11
+    LD [I], V0
+    LD V0, [I]
+    LD V4, 0
+    SNE V0, 0x22
+    LD V4, 1
+
+; Display wait: only a waiting emulator draws fewer than 10 sprites in 3 frames
+    CLS
+    LD V0, 3
+    LD DT, V0
+    LD V1, 0
+    LD V2, 0
+    LD I, dot
+wait_loop:
+    DRW V2, V2, 1
+    ADD V1, 1
+    SNE V1, 10
+    JP wait_fast
+    LD V0, DT
+    SE V0, 0
+    JP wait_loop
+    LD V5, 1
+    JP wait_done
+wait_fast:
+    LD V5, 0
+wait_done:
+
+; Wrapping: a sprite at the right edge collides with one at the left edge
+    CLS
+    LD V0, 60
+    LD V1, 0
+    LD I, bar
+    DRW V0, V1, 1
+    LD I, dot
+    DRW V1, V1, 1
+    LD V6, VF
+
+; Shifting: VX holds 0x04 and VY 0x40, the result shows which one was shifted
+    LD V0, 0x04
+    LD V1, 0x40
+    SHR V0, V1
+    LD V2, 0xF0
+    AND V2, V0
+    LD V7, 0
+    SNE V2, 0
+    LD V7, 1
+
+; Report
+    CLS
+    LD VE, 0
+    LD VD, 0
+    LD VA, 0
+    LD VB, 1
+    LD VC, V3
+    CALL report
+    LD VB, 2
+    LD VC, V4
+    CALL report
+    LD VB, 3
+    LD VC, V5
+    CALL report
+    LD VB, 4
+    LD VC, V6
+    CALL report
+    LD VB, 5
+    LD VC, V7
+    CALL report
+    LD VB, 6
+    LD VC, V8
+    CALL report
+
+end:
+    JP end
+
+buffer:
+    DB 0x11, 0x22
+
+dot:
+    DB 0x80
+
+bar:
+    DB 0xFF
+
+    INCLUDE "report.inc"